@@ -1,37 +1,46 @@
-use aoc2025::get_input;
-use bstr::io::BufReadExt as _;
+use anyhow::{Context as _, Result, anyhow};
+use aoc2025::{get_input, init_logging};
+use bstr::ByteSlice as _;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::prelude::*;
 
-fn main() {
+fn main() -> Result<()> {
+    init_logging();
     let input1 = get_input("input_d1.txt");
+    let mut instructions = File::open(&input1)?;
     let mut d = Dial::new();
-    let mut instructions = File::open(&input1).unwrap();
-    let turns = get_instructions(&mut instructions);
-    let zeros = d.do_all(&turns);
+    let zeros = d.do_all(Instructions::new(BufReader::new(&mut instructions)))?;
     println!("Day 1 part 1: {}", zeros);
 
+    instructions.seek(std::io::SeekFrom::Start(0))?;
     let mut d2 = DialPt2::new();
-    let zeros2 = d2.do_all(&turns);
+    let zeros2 = d2.do_all(Instructions::new(BufReader::new(&mut instructions)))?;
     println!("Day 1 part 2: {}", zeros2);
+    Ok(())
 }
 
+/// A signed twist of the dial: positive is clockwise (R), negative is counter-clockwise (L).
+type Turn = i64;
+
 struct Dial {
     n: i64,
-    zeros: u32,
+    zeros: u64,
 }
 
 const DIAL_SIZE: i64 = 100;
 
 trait HasDial {
     fn rotate(&mut self, twist: i64);
-    fn get_zeros(&self) -> u32;
-    fn do_all(&mut self, turns: &[i64]) -> u32 {
-        for &turn in turns {
-            self.rotate(turn);
+    fn get_zeros(&self) -> u64;
+    fn do_all<I>(&mut self, turns: I) -> Result<u64>
+    where
+        I: IntoIterator<Item = Result<Turn>>,
+    {
+        for turn in turns {
+            self.rotate(turn?);
         }
-        self.get_zeros()
+        Ok(self.get_zeros())
     }
 }
 
@@ -42,20 +51,20 @@ impl Dial {
 }
 impl HasDial for Dial {
     fn rotate(&mut self, twist: i64) {
-        self.n += twist;
-        if self.n % DIAL_SIZE == 0 {
+        self.n = (self.n + twist.rem_euclid(DIAL_SIZE)) % DIAL_SIZE;
+        if self.n == 0 {
             self.zeros += 1;
         }
     }
 
-    fn get_zeros(&self) -> u32 {
+    fn get_zeros(&self) -> u64 {
         self.zeros
     }
 }
 
 struct DialPt2 {
     n: i64,
-    zeros: u32,
+    zeros: u64,
 }
 
 impl DialPt2 {
@@ -66,38 +75,81 @@ impl DialPt2 {
 
 impl HasDial for DialPt2 {
     fn rotate(&mut self, twist: i64) {
-        if self.n * (self.n + twist) < 0 {
+        // Widened so that even an i64::MAX twist can't overflow
+        let end = self.n as i128 + twist as i128;
+        if self.n as i128 * end < 0 {
             // crossed zero
             self.zeros += 1;
         }
-        self.n += twist;
         // Add full laps
-        self.zeros += (self.n.abs() / DIAL_SIZE) as u32;
-        if twist != 0 && self.n == 0 {
+        self.zeros += (end.unsigned_abs() / DIAL_SIZE as u128) as u64;
+        if twist != 0 && end == 0 {
             // Landed at zero
             self.zeros += 1;
         }
-        self.n %= DIAL_SIZE;
+        self.n = (end % DIAL_SIZE as i128) as i64;
     }
 
-    fn get_zeros(&self) -> u32 {
+    fn get_zeros(&self) -> u64 {
         self.zeros
     }
 }
 
-fn get_instructions<R: Read>(rdr: &mut R) -> Vec<i64> {
-    let lrdr = BufReader::new(rdr);
-    let mut turns = Vec::new();
-    for line in lrdr.byte_lines() {
-        let line = line.unwrap();
-        let turn = match &line[0..1] {
-            b"R" => str::parse(str::from_utf8(&line[1..]).unwrap()).unwrap(),
-            b"L" => -str::parse::<i64>(str::from_utf8(&line[1..]).unwrap()).unwrap(),
-            _ => panic!("Bad input line"),
-        };
-        turns.push(turn);
+/// Lazily reads one turn per line, reusing a single line buffer so that
+/// arbitrarily long instruction logs are processed in constant memory.
+///
+/// Accepted forms are `R48`, `L5`, `R +48`, `L-5` (a negative left turn is a
+/// right turn) and bare signed counts like `-5`. Surrounding whitespace is
+/// ignored, as are blank lines and lines starting with `#`.
+struct Instructions<R> {
+    rdr: R,
+    buf: Vec<u8>,
+    line_no: usize,
+}
+
+impl<R: BufRead> Instructions<R> {
+    fn new(rdr: R) -> Self {
+        Self {
+            rdr,
+            buf: Vec::new(),
+            line_no: 0,
+        }
     }
-    turns
+}
+
+impl<R: BufRead> Iterator for Instructions<R> {
+    type Item = Result<Turn>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+            match self.rdr.read_until(b'\n', &mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => self.line_no += 1,
+                Err(e) => return Some(Err(e.into())),
+            }
+            let line = self.buf.trim();
+            if line.is_empty() || line.starts_with(b"#") {
+                continue;
+            }
+            let line_no = self.line_no;
+            return Some(parse_turn(line).with_context(|| format!("Line {}", line_no)));
+        }
+    }
+}
+
+fn parse_turn(line: &[u8]) -> Result<Turn> {
+    let (direction, count) = match line[0] {
+        b'R' | b'r' => (1, &line[1..]),
+        b'L' | b'l' => (-1, &line[1..]),
+        _ => (1, line),
+    };
+    let count: i64 = str::from_utf8(count.trim())?
+        .parse()
+        .with_context(|| format!("Bad turn {:?}", line.as_bstr()))?;
+    count
+        .checked_mul(direction)
+        .ok_or_else(|| anyhow!("Turn {:?} overflows", line.as_bstr()))
 }
 
 #[cfg(test)]
@@ -119,19 +171,46 @@ L282
 
     #[test]
     fn test_day_1_pt1() {
-        let mut input_file = std::io::Cursor::new(INPUT.trim().to_owned());
-        let turns = get_instructions(&mut input_file);
+        let turns = Instructions::new(INPUT.trim().as_bytes());
         let mut d = Dial::new();
-        let zeros = d.do_all(&turns);
+        let zeros = d.do_all(turns).unwrap();
         assert_eq!(zeros, 3);
     }
 
     #[test]
     fn test_day_1_pt2() {
-        let mut input_file = std::io::Cursor::new(INPUT.trim().to_owned());
-        let turns = get_instructions(&mut input_file);
+        let turns = Instructions::new(INPUT.trim().as_bytes());
         let mut d = DialPt2::new();
-        let zeros = d.do_all(&turns);
+        let zeros = d.do_all(turns).unwrap();
         assert_eq!(zeros, 8);
     }
+
+    #[test]
+    fn test_huge_turns() {
+        static HUGE: &str = "R9223372036854775807\nL9223372036854775807\nR50\n";
+        let mut d = Dial::new();
+        assert_eq!(d.do_all(Instructions::new(HUGE.as_bytes())).unwrap(), 1);
+        // Each twist passes zero once per 100 clicks, from the 50th or 57th on.
+        let mut d = DialPt2::new();
+        let zeros = d.do_all(Instructions::new(HUGE.as_bytes())).unwrap();
+        assert_eq!(zeros, 2 * 92233720368547758 + 1);
+    }
+
+    #[test]
+    fn test_instruction_forms() {
+        static FORMS: &str = "# warm-up\n  R48  \n\nL5\nR +12\nL-7\n-3\n+1000\nr2\n";
+        let turns: Vec<Turn> = Instructions::new(FORMS.as_bytes())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(turns, vec![48, -5, 12, 7, -3, 1000, 2]);
+    }
+
+    #[test]
+    fn test_bad_instruction() {
+        let mut turns = Instructions::new("R1\n# note\nX12\n".as_bytes());
+        assert_eq!(turns.next().unwrap().unwrap(), 1);
+        let err = turns.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "Line 3");
+        assert!(turns.next().is_none());
+    }
 }
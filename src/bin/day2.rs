@@ -1,29 +1,41 @@
-use anyhow::{Context as _, Result, anyhow};
-use aoc2025::get_input_string;
-use ilog::IntLog as _;
+use anyhow::{Context as _, Result, anyhow, bail};
+use aoc2025::{get_input_string, init_logging, map_items, proper_divisors, run_compared};
+use num_traits::{PrimInt, Unsigned, checked_pow};
 use regex::Regex;
+use std::fmt::Debug;
+use std::num::ParseIntError;
+use std::str::FromStr;
 use std::sync::LazyLock;
 
 static WHITESPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
 
+/// An unsigned integer type that IDs can be held in.
+pub trait IdInt:
+    PrimInt + Unsigned + From<u32> + Into<u128> + FromStr<Err = ParseIntError> + Debug + Send + Sync
+{
+}
+
+impl IdInt for u64 {}
+impl IdInt for u128 {}
+
 #[derive(Debug)]
-struct Range {
-    first: u64,
-    last: u64,
+pub struct Range<T = u64> {
+    pub first: T,
+    pub last: T,
 }
 
-impl Range {
-    fn from_expr(rexpr: &str) -> Result<Range> {
+impl<T: IdInt> Range<T> {
+    fn from_expr(rexpr: &str) -> Result<Self> {
         let (sfirst, slast) = rexpr
             .split_once('-')
             .context(anyhow!("Bad range {}", rexpr))?;
-        let first: u64 = sfirst.parse()?;
-        let last: u64 = slast.parse()?;
+        let first: T = sfirst.parse()?;
+        let last: T = slast.parse()?;
         Ok(Range { first, last })
     }
 }
 
-fn input_to_ranges(inputstr: &str) -> Result<Vec<Range>> {
+fn input_to_ranges<T: IdInt>(inputstr: &str) -> Result<Vec<Range<T>>> {
     let mut ranges = Vec::new();
     let compacted = WHITESPACE.replace_all(inputstr, "");
     let range_exprs = compacted.split(',');
@@ -33,126 +45,422 @@ fn input_to_ranges(inputstr: &str) -> Result<Vec<Range>> {
     Ok(ranges)
 }
 
-fn num_digits_of(val: u64) -> u32 {
-    val.log10() as u32 + 1
+/// Which IDs count as invalid. No rule treats a single-digit ID as invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatRule {
+    /// Some pattern repeated exactly k times, like 123123 for k = 2. Part 1 is `Exactly(2)`.
+    /// Note 111111 also counts for k = 2, being 111 twice.
+    Exactly(u32),
+    /// Some pattern repeated k or more times. Part 2 is `AtLeast(2)`.
+    AtLeast(u32),
+    /// Some pattern repeated between k and m times, inclusive.
+    Between(u32, u32),
+    /// Digits that read the same forwards and backwards.
+    Palindrome,
+    /// Digits left unchanged by rotating them by less than their full length.
+    /// That only happens for repeated patterns, so this is the same set as `AtLeast(2)`.
+    RotationInvariant,
 }
 
-fn make_number(repeated: u64, num_rpt_digits: u32, num_repeats: u32) -> u64 {
-    let mut new_num = repeated;
-    for _ in 0..num_repeats - 1 {
-        new_num = new_num * 10u64.pow(num_rpt_digits) + repeated;
+impl RepeatRule {
+    /// Pattern lengths which make a `num_digits`-digit ID invalid, or None if
+    /// the rule isn't about repeated patterns.
+    fn pattern_lengths(self, num_digits: u32) -> Option<impl Iterator<Item = u32> + Clone> {
+        let (min_repeats, max_repeats) = match self {
+            Self::Exactly(k) => (k, k),
+            Self::AtLeast(k) => (k, u32::MAX),
+            Self::Between(k, m) => (k, m),
+            Self::RotationInvariant => (2, u32::MAX),
+            Self::Palindrome => return None,
+        };
+        // A pattern "repeated" once is just the ID itself.
+        let repeats = min_repeats.max(2)..=max_repeats;
+        Some(
+            proper_divisors(num_digits)
+                .iter()
+                .copied()
+                .filter(move |&num_rpt_digits| repeats.contains(&(num_digits / num_rpt_digits))),
+        )
     }
-    new_num
-}
 
-fn next_invalid_id(val: u64) -> u64 {
-    let digits = num_digits_of(val);
-    if digits % 2 == 1 {
-        // Odd number
-        let next_bigger_left_dig = digits.div_ceil(2);
-        let pattern = 10u64.pow(next_bigger_left_dig - 1);
-        return 10u64.pow(next_bigger_left_dig * 2 - 1) + pattern;
-    }
-    let mut pat_digs = digits / 2;
-    let pat_scale = 10u64.pow(pat_digs);
-    let mut pat = val / pat_scale;
-    let mut next_val = make_number(pat, pat_digs, 2);
-    if next_val <= val {
-        pat += 1;
-        pat_digs = num_digits_of(pat); // Just in case pat went from, say, 99 to 100.
-        next_val = make_number(pat, pat_digs, 2);
-    }
-    next_val
+    /// Weighted pattern lengths whose repeated-pattern IDs, summed with those
+    /// weights, count each invalid `num_digits`-digit ID exactly once. None if
+    /// the rule isn't about repeated patterns.
+    ///
+    /// An ID is invalid when its shortest repeating pattern divides one of the
+    /// `pattern_lengths`, and IDs repeating a q-digit pattern are those whose
+    /// shortest pattern divides q, so Möbius inversion gives the weights.
+    fn pattern_terms(self, num_digits: u32) -> Option<Vec<(u32, i32)>> {
+        let rpt_lens = self.pattern_lengths(num_digits)?;
+        let divisors = proper_divisors(num_digits);
+        let shortest: Vec<u32> = divisors
+            .iter()
+            .copied()
+            .filter(|&p| rpt_lens.clone().any(|r| r.is_multiple_of(p)))
+            .collect();
+        Some(
+            divisors
+                .iter()
+                .map(|&q| {
+                    let weight = shortest
+                        .iter()
+                        .filter(|p| p.is_multiple_of(q))
+                        .map(|p| mobius(p / q))
+                        .sum();
+                    (q, weight)
+                })
+                .filter(|&(_, weight)| weight != 0)
+                .collect(),
+        )
+    }
 }
 
-fn sum_invalid(ranges: &[Range], pt2: bool) -> u64 {
-    let mut sum = 0;
-    for r in ranges {
-        let mut iid = r.first - 1;
-        loop {
-            iid = if pt2 {
-                next_invalid_id_pt2(iid)
+/// The base IDs are written in when looking for repeated digit patterns.
+/// All arithmetic is overflow-checked, with None meaning "doesn't fit in T".
+#[derive(Debug, Clone, Copy)]
+pub struct Radix(u32);
+
+pub const DECIMAL: Radix = Radix(10);
+
+impl Radix {
+    pub fn new(radix: u32) -> Result<Self> {
+        if !(2..=36).contains(&radix) {
+            bail!("Radix {} is not in 2..=36", radix);
+        }
+        Ok(Self(radix))
+    }
+
+    fn base<T: IdInt>(self) -> T {
+        <T as From<u32>>::from(self.0)
+    }
+
+    fn pow<T: IdInt>(self, exp: u32) -> Option<T> {
+        checked_pow(self.base(), exp as usize)
+    }
+
+    fn num_digits_of<T: IdInt>(self, val: T) -> u32 {
+        let mut digits = 1;
+        let mut rest = val / self.base();
+        while rest > T::zero() {
+            digits += 1;
+            rest = rest / self.base();
+        }
+        digits
+    }
+
+    /// Repeats `repeated` `num_repeats` times, or None if the result doesn't fit in a T.
+    fn make_number<T: IdInt>(
+        self,
+        repeated: T,
+        num_rpt_digits: u32,
+        num_repeats: u32,
+    ) -> Option<T> {
+        let scale = self.pow(num_rpt_digits)?;
+        let mut new_num = repeated;
+        for _ in 0..num_repeats - 1 {
+            new_num = new_num.checked_mul(&scale)?.checked_add(&repeated)?;
+        }
+        Some(new_num)
+    }
+
+    /// Whether `val` is an invalid ID under `rule`.
+    pub fn is_invalid<T: IdInt>(self, val: T, rule: RepeatRule) -> bool {
+        let num_digits = self.num_digits_of(val);
+        if num_digits < 2 {
+            return false;
+        }
+        let Some(mut rpt_lens) = rule.pattern_lengths(num_digits) else {
+            let left = val / self.pow(num_digits / 2).unwrap();
+            return self.mirror(left, num_digits) == Some(val);
+        };
+        rpt_lens.any(|num_rpt_digits| {
+            let num_repeats = num_digits / num_rpt_digits;
+            let pattern = val / self.pow(num_rpt_digits * (num_repeats - 1)).unwrap();
+            self.make_number(pattern, num_rpt_digits, num_repeats) == Some(val)
+        })
+    }
+
+    // Strategy: Any number < 10 has a next invalid number of 11.
+    // Find all two-number factorizations of the number of digits (num_rpt_digits, num_reps), plus (1, N),
+    // keeping those with a number of repeats the rule allows.
+    // For each of these factorizations, find the smallest of the next-invalid numbers.
+    //   Call next-invalid up to twice per factorization: Try with original patn, keep that if it's > the original. Else add 1.
+    //   Edge case: if all 9's, there well be no next-invalid because in each factorization of digits, the pattern will be all 9's.
+    //   Likewise if the rule allows no factorization of this many digits. Either way, move on to more digits.
+
+    /// The smallest ID greater than `val` that is invalid under `rule`, if it fits in a T.
+    fn next_invalid<T: IdInt>(self, val: T, rule: RepeatRule) -> Option<T> {
+        let num_digits = self.num_digits_of(val);
+        let Some(rpt_lens) = rule.pattern_lengths(num_digits) else {
+            return self.next_palindrome(val);
+        };
+        rpt_lens
+            .filter_map(|num_rpt_digits| {
+                self.next_potential_invalid(val, num_digits, num_rpt_digits)
+            })
+            .min()
+            .or_else(|| {
+                // Get smallest value with the next-highest number of digits that has one.
+                (num_digits + 1..=self.num_digits_of(T::max_value())).find_map(|more_digits| {
+                    rule.pattern_lengths(more_digits)?
+                        .filter_map(|num_rpt_digits| {
+                            let pat = self.pow(num_rpt_digits - 1)?;
+                            self.make_number(pat, num_rpt_digits, more_digits / num_rpt_digits)
+                        })
+                        .min()
+                })
+            })
+    }
+
+    fn next_potential_invalid<T: IdInt>(
+        self,
+        val: T,
+        num_digits: u32,
+        num_rpt_digits: u32,
+    ) -> Option<T> {
+        let num_repeats = num_digits / num_rpt_digits;
+        let baseval = val / self.pow(num_rpt_digits * (num_repeats - 1))?;
+        assert_eq!(self.num_digits_of(baseval), num_rpt_digits); // Cheap enough, may as well
+        let first_try = self.make_number(baseval, num_rpt_digits, num_repeats)?;
+        if first_try > val {
+            return Some(first_try);
+        }
+        let next_base = baseval + T::one();
+        if self.num_digits_of(next_base) == num_rpt_digits {
+            self.make_number(next_base, num_rpt_digits, num_repeats)
+        } else {
+            None
+        }
+    }
+
+    /// Appends the digits of `left`, the leading half of a `num_digits`-digit
+    /// palindrome, in reverse. The middle digit isn't repeated for odd lengths.
+    fn mirror<T: IdInt>(self, left: T, num_digits: u32) -> Option<T> {
+        let base = self.base();
+        let mut palindrome = left;
+        let mut rest = if num_digits % 2 == 1 {
+            left / base
+        } else {
+            left
+        };
+        for _ in 0..num_digits / 2 {
+            palindrome = palindrome.checked_mul(&base)?.checked_add(&(rest % base))?;
+            rest = rest / base;
+        }
+        Some(palindrome)
+    }
+
+    /// The smallest palindrome with at least two digits greater than `val`, if it fits in a T.
+    fn next_palindrome<T: IdInt>(self, val: T) -> Option<T> {
+        if val < self.base() {
+            return self.make_number(T::one(), 1, 2);
+        }
+        let num_digits = self.num_digits_of(val);
+        let left = val / self.pow(num_digits / 2)?;
+        let first_try = self.mirror(left, num_digits)?;
+        if first_try > val {
+            return Some(first_try);
+        }
+        let next_left = left + T::one();
+        if self.num_digits_of(next_left) == self.num_digits_of(left) {
+            self.mirror(next_left, num_digits)
+        } else {
+            // All 9's, so the next is 10...01
+            self.pow::<T>(num_digits)?.checked_add(&T::one())
+        }
+    }
+
+    /// Count and sum of the IDs in `first..=last` made by repeating a
+    /// `num_rpt_digits`-digit pattern out to `num_digits` digits. Each is the
+    /// pattern times 10..010..01, so together they form an arithmetic progression.
+    /// The sum is None if it overflows a u128.
+    fn progression<T: IdInt>(
+        self,
+        first: T,
+        last: T,
+        num_digits: u32,
+        num_rpt_digits: u32,
+    ) -> (u128, Option<u128>) {
+        let Some(mult) = self.make_number(T::one(), num_rpt_digits, num_digits / num_rpt_digits)
+        else {
+            return (0, Some(0));
+        };
+        let first_pat = first / mult
+            + if first % mult == T::zero() {
+                T::zero()
             } else {
-                next_invalid_id(iid)
+                T::one()
             };
-            if iid <= r.last {
-                sum += iid;
-            } else {
-                break;
-            }
+        let lo: u128 = self
+            .pow::<T>(num_rpt_digits - 1)
+            .unwrap()
+            .max(first_pat)
+            .into();
+        let hi: u128 = (self.pow::<T>(num_rpt_digits).unwrap() - T::one())
+            .min(last / mult)
+            .into();
+        if lo > hi {
+            return (0, Some(0));
         }
+        // Patterns are at most half as wide as T, so these can't overflow.
+        let count = hi - lo + 1;
+        let pair_sum = lo + hi;
+        let half_sum = if count.is_multiple_of(2) {
+            (count / 2).checked_mul(pair_sum)
+        } else {
+            (pair_sum / 2).checked_mul(count)
+        };
+        let mult: u128 = mult.into();
+        (
+            count,
+            half_sum.and_then(|half_sum| half_sum.checked_mul(mult)),
+        )
     }
-    sum
 }
 
-// ----- Part 2
-// Strategy: Any number < 10 has a next invalid number of 11.
-// Find all two-number factorizations of the number of digits (num_rpt_digits, num_reps), plus (1, N).
-// For each of these factorizations, find the smallest of the next-invalid numbers.
-//   Call next-invalid up to twice per factorization: Try with original patn, keep that if it's > the original. Else add 1.
-//   Edge case: if all 9's, there well be no next-invalid because in each factorization of digits, the pattern will be all 9's.
-
-fn next_potential_invalid(val: u64, num_digits: u32, num_rpt_digits: u32) -> Option<u64> {
-    let num_repeats = num_digits / num_rpt_digits;
-    let baseval = val / 10u64.pow(num_rpt_digits * (num_repeats - 1));
-    assert_eq!(num_digits_of(baseval), num_rpt_digits); // Cheap enough, may as well
-    let first_try = make_number(baseval, num_rpt_digits, num_repeats);
-    if first_try > val {
-        return Some(first_try);
-    }
-    let next_base = baseval + 1;
-    if num_digits_of(next_base) == num_rpt_digits {
-        Some(make_number(next_base, num_rpt_digits, num_repeats))
-    } else {
-        None
-    }
+/// Iterates, in increasing order, over the invalid IDs within a `Range`.
+///
+/// For repeated-pattern rules `count` and `sum` are computed in closed form
+/// rather than by stepping through every ID, so they are instant even for
+/// ranges spanning billions of IDs. Palindromes are counted one by one.
+pub struct InvalidIds<T = u64> {
+    next: Option<T>,
+    last: T,
+    radix: Radix,
+    rule: RepeatRule,
 }
 
-/// Returns a list of num_rpt_digits
-fn get_factorizations(num_digits: u32) -> Vec<u32> {
-    let factors = prime_factorization::Factorization::run(num_digits).factors;
-    // BTreeSet is nice here because reproducible ordering helps unit testing.
-    let mut num_rpt_digits_set = std::collections::BTreeSet::new();
-    for some_factors in 1..factors.len() {
-        for factor_list in gen_combinations::CombinationIterator::new(&factors, some_factors) {
-            num_rpt_digits_set.insert(factor_list.iter().map(|i| **i).product::<u32>());
+impl<T: IdInt> InvalidIds<T> {
+    pub fn new(r: &Range<T>, rule: RepeatRule) -> Self {
+        Self::with_radix(r, DECIMAL, rule)
+    }
+
+    pub fn with_radix(r: &Range<T>, radix: Radix, rule: RepeatRule) -> Self {
+        // Zero is never invalid, so starting the search from it is safe.
+        let next = radix.next_invalid(r.first.saturating_sub(T::one()), rule);
+        Self {
+            next,
+            last: r.last,
+            radix,
+            rule,
+        }
+    }
+
+    /// Sum of the remaining invalid IDs, or None if it overflows a u128.
+    pub fn total(mut self) -> Option<u128> {
+        let Some(progressions) = self.progressions() else {
+            return self.try_fold(0u128, |total, iid| total.checked_add(iid.into()));
+        };
+        let (mut plus, mut minus) = (0u128, 0u128);
+        for (weight, _, sum) in progressions {
+            let weighted = sum?.checked_mul(weight.unsigned_abs() as u128)?;
+            if weight > 0 {
+                plus = plus.checked_add(weighted)?;
+            } else {
+                minus = minus.checked_add(weighted)?;
+            }
         }
+        Some(plus - minus)
     }
-    let mut factorizations = Vec::new();
-    factorizations.reserve(num_rpt_digits_set.len() + 1);
-    factorizations.push(1);
-    for rpt_digits in num_rpt_digits_set {
-        factorizations.push(rpt_digits);
+
+    /// Weighted (weight, count, sum) terms which total the remaining invalid
+    /// IDs, one per (number of digits, pattern length) pair. None if the rule
+    /// has no closed form.
+    ///
+    /// Part 1 has a single pattern length per even digit count. For part 2 an
+    /// ID like 111111 repeats patterns of 1, 2 and 3 digits, so the pattern
+    /// lengths are combined by inclusion–exclusion.
+    fn progressions(&self) -> Option<impl Iterator<Item = (i32, u128, Option<u128>)> + use<T>> {
+        if self.rule == RepeatRule::Palindrome {
+            return None;
+        }
+        let (first, last) = match self.next {
+            Some(first) if first <= self.last => (first, self.last),
+            _ => (T::one(), T::zero()),
+        };
+        let (radix, rule) = (self.radix, self.rule);
+        Some(
+            (2..=radix.num_digits_of(T::max_value())).flat_map(move |num_digits| {
+                let terms = rule.pattern_terms(num_digits).unwrap();
+                terms.into_iter().map(move |(num_rpt_digits, weight)| {
+                    let (count, sum) = radix.progression(first, last, num_digits, num_rpt_digits);
+                    (weight, count, sum)
+                })
+            }),
+        )
     }
-    factorizations
 }
 
-fn next_invalid_id_pt2(val: u64) -> u64 {
-    if val < 10 {
-        return 11;
+impl<T: IdInt> Iterator for InvalidIds<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let iid = self.next.filter(|&iid| iid <= self.last)?;
+        self.next = self.radix.next_invalid(iid, self.rule);
+        Some(iid)
+    }
+
+    fn count(self) -> usize {
+        let Some(progressions) = self.progressions() else {
+            return self.fold(0, |count, _| count + 1);
+        };
+        let (plus, minus) =
+            progressions.fold((0u128, 0u128), |(plus, minus), (weight, count, _)| {
+                let weighted = count * weight.unsigned_abs() as u128;
+                if weight > 0 {
+                    (plus + weighted, minus)
+                } else {
+                    (plus, minus + weighted)
+                }
+            });
+        usize::try_from(plus - minus).expect("Too many invalid IDs to count")
     }
-    let num_digits = num_digits_of(val);
-    let potentials: Vec<u64> = get_factorizations(num_digits)
+}
+
+fn sum_invalid<T: IdInt>(ranges: &[Range<T>], rule: RepeatRule, parallel: bool) -> Result<u128> {
+    map_items(ranges, parallel, |r| InvalidIds::new(r, rule).total())
         .into_iter()
-        .filter_map(|num_rpt_digits| next_potential_invalid(val, num_digits, num_rpt_digits))
-        .collect();
-    potentials.into_iter().min().unwrap_or_else(|| {
-        // val must have been 9999...9. Get smallest value with the next-highest number of digits
-        let factorizations = get_factorizations(num_digits + 1);
-        // These are ordered from lowest (always 1) to highest. Pick the highest.
-        let num_rpt_digits = factorizations[factorizations.len() - 1];
-        let pat = 10u64.pow(num_rpt_digits - 1);
-        make_number(pat, num_rpt_digits, (num_digits + 1) / num_rpt_digits)
-    })
+        .try_fold(0u128, |total, sum| {
+            sum.and_then(|sum| total.checked_add(sum))
+        })
+        .context("Invalid ID sum overflows a u128")
+}
+
+/// The Möbius function, which `RepeatRule::pattern_terms` uses to weight
+/// the pattern lengths.
+fn mobius(mut n: u32) -> i32 {
+    let mut sign = 1;
+    let mut p = 2;
+    while p * p <= n {
+        if n.is_multiple_of(p) {
+            n /= p;
+            if n.is_multiple_of(p) {
+                return 0;
+            }
+            sign = -sign;
+        }
+        p += 1;
+    }
+    if n > 1 {
+        sign = -sign;
+    }
+    sign
 }
 
 fn main() -> Result<()> {
+    init_logging();
     let ranges_str = get_input_string("input_d2.txt")?;
-    let ranges = input_to_ranges(&ranges_str)?;
-    let sum = sum_invalid(&ranges, false);
+    let ranges: Vec<Range> = input_to_ranges(&ranges_str)?;
+    let sum = run_compared("Day 2 pt 1", |parallel| {
+        sum_invalid(&ranges, RepeatRule::Exactly(2), parallel).ok()
+    })
+    .context("Invalid ID sum overflows a u128")?;
     println!("Pt 1: IID sum = {}", sum);
 
-    let sum2 = sum_invalid(&ranges, true);
+    let sum2 = run_compared("Day 2 pt 2", |parallel| {
+        sum_invalid(&ranges, RepeatRule::AtLeast(2), parallel).ok()
+    })
+    .context("Invalid ID sum overflows a u128")?;
     println!("Pt 2: IID sum = {}", sum2);
     Ok(())
 }
@@ -162,6 +470,7 @@ fn main() -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use RepeatRule::*;
 
     static INPUT: &str = r#"
 11-22,95-115,998-1012,1188511880-1188511890,222220-222224,
@@ -171,42 +480,229 @@ mod tests {
 
     #[test]
     fn test_num_digits_of() {
-        assert_eq!(num_digits_of(99), 2);
-        assert_eq!(num_digits_of(100), 3);
+        assert_eq!(DECIMAL.num_digits_of(99u64), 2);
+        assert_eq!(DECIMAL.num_digits_of(100u64), 3);
+        assert_eq!(DECIMAL.num_digits_of(u128::MAX), 39);
+        assert_eq!(Radix::new(2).unwrap().num_digits_of(8u64), 4);
     }
 
     #[test]
     fn test_next_inval_id() {
-        assert_eq!(next_invalid_id(333), 1010);
-        assert_eq!(next_invalid_id(3300), 3333);
-        assert_eq!(next_invalid_id(3333), 3434);
-        assert_eq!(next_invalid_id(9999), 100100);
+        assert_eq!(DECIMAL.next_invalid(333u64, Exactly(2)), Some(1010));
+        assert_eq!(DECIMAL.next_invalid(3300u64, Exactly(2)), Some(3333));
+        assert_eq!(DECIMAL.next_invalid(3333u64, Exactly(2)), Some(3434));
+        assert_eq!(DECIMAL.next_invalid(9999u64, Exactly(2)), Some(100100));
+        assert_eq!(DECIMAL.next_invalid(u64::MAX, Exactly(2)), None);
+        assert_eq!(
+            DECIMAL.next_invalid(u64::MAX as u128, Exactly(2)),
+            Some(18446744081844674408)
+        );
     }
 
     #[test]
     fn test_pt1() {
-        let ranges = input_to_ranges(INPUT).unwrap();
+        let ranges: Vec<Range> = input_to_ranges(INPUT).unwrap();
         assert_eq!(ranges.len(), 11);
-        let sum = sum_invalid(&ranges, false);
+        let sum = sum_invalid(&ranges, Exactly(2), false).unwrap();
         assert_eq!(sum, 1227775554);
     }
 
     #[test]
-    fn test_get_factorizations() {
-        assert_eq!(get_factorizations(12), vec![1, 2, 3, 4, 6]);
-        assert_eq!(get_factorizations(2), vec![1]);
+    fn test_all_nines_pt2() {
+        assert_eq!(DECIMAL.next_invalid(9999999u64, AtLeast(2)), Some(10001000));
     }
 
     #[test]
-    fn test_all_nines_pt2() {
-        assert_eq!(next_invalid_id_pt2(9999999), 10001000);
+    fn test_invalid_ids_iter() {
+        let r: Range = Range {
+            first: 95,
+            last: 115,
+        };
+        assert_eq!(
+            InvalidIds::new(&r, Exactly(2)).collect::<Vec<_>>(),
+            vec![99]
+        );
+        assert_eq!(
+            InvalidIds::new(&r, AtLeast(2)).collect::<Vec<_>>(),
+            vec![99, 111]
+        );
+
+        let from_zero: Range = Range { first: 0, last: 33 };
+        assert_eq!(
+            InvalidIds::new(&from_zero, Exactly(2)).collect::<Vec<_>>(),
+            vec![11, 22, 33]
+        );
+        assert_eq!(InvalidIds::new(&from_zero, AtLeast(2)).count(), 3);
     }
 
     #[test]
-    fn test_pt2() {
-        let ranges = input_to_ranges(INPUT).unwrap();
-        assert_eq!(ranges.len(), 11);
-        let sum = sum_invalid(&ranges, true);
-        assert_eq!(sum, 4174379265);
+    fn test_invalid_ids_to_max() {
+        let r: Range = Range {
+            first: 18446744060000000000,
+            last: u64::MAX,
+        };
+        for rule in [Exactly(2), AtLeast(2)] {
+            let ids: Vec<u64> = InvalidIds::new(&r, rule).collect();
+            assert_eq!(ids.last(), Some(&18446744071844674407));
+            assert_eq!(InvalidIds::new(&r, rule).count(), ids.len());
+            let sum: u128 = ids.iter().map(|&i| i as u128).sum();
+            assert_eq!(InvalidIds::new(&r, rule).total(), Some(sum));
+        }
+    }
+
+    #[test]
+    fn test_pt1_closed_form() {
+        let r: Range = Range {
+            first: 0,
+            last: u64::MAX,
+        };
+        // 9 + 90 + 900 + ... two-digit to 18-digit, plus 1000000000..=1844674407 at 20 digits.
+        assert_eq!(
+            InvalidIds::new(&r, Exactly(2)).count(),
+            999999999 + 844674408
+        );
+        let mut ids = InvalidIds::new(
+            &Range {
+                first: 10u64,
+                last: 1000,
+            },
+            Exactly(2),
+        );
+        ids.next();
+        assert_eq!(ids.total(), Some(22 + 33 + 44 + 55 + 66 + 77 + 88 + 99));
+    }
+
+    #[test]
+    fn test_mobius() {
+        let mus: Vec<i32> = (1..=12).map(mobius).collect();
+        assert_eq!(mus, vec![1, -1, -1, 0, -1, 1, -1, 0, 0, 1, -1, 0]);
+    }
+
+    #[test]
+    fn test_closed_form_matches_enumeration() {
+        for (first, last) in [
+            (0, 10_000_000),
+            (123_456, 98_765_432),
+            (1_000_000_000, 1_100_000_000),
+        ] {
+            let r: Range = Range { first, last };
+            for rule in [Exactly(2), AtLeast(2)] {
+                let ids: Vec<u64> = InvalidIds::new(&r, rule).collect();
+                assert_eq!(InvalidIds::new(&r, rule).count(), ids.len());
+                let sum: u128 = ids.iter().map(|&i| i as u128).sum();
+                assert_eq!(InvalidIds::new(&r, rule).total(), Some(sum));
+            }
+        }
+    }
+
+    #[test]
+    fn test_pt2_closed_form_whole_range() {
+        let r: Range = Range {
+            first: 0,
+            last: u64::MAX,
+        };
+        let pt1 = InvalidIds::new(&r, Exactly(2)).count();
+        let pt2 = InvalidIds::new(&r, AtLeast(2)).count();
+        assert!(pt2 > pt1);
+        // Only six-digit IDs: 900 three-digit patterns and 90 two-digit patterns,
+        // less the 9 single-digit repeats counted by both.
+        let six: Range = Range {
+            first: 100_000,
+            last: 999_999,
+        };
+        assert_eq!(InvalidIds::new(&six, AtLeast(2)).count(), 900 + 90 - 9);
+    }
+
+    #[test]
+    fn test_is_invalid() {
+        assert!(DECIMAL.is_invalid(1212u64, Exactly(2)));
+        assert!(!DECIMAL.is_invalid(121212u64, Exactly(2)));
+        assert!(DECIMAL.is_invalid(121212u64, AtLeast(2)));
+        assert!(!DECIMAL.is_invalid(7u64, AtLeast(2)));
+        let hex = Radix::new(16).unwrap();
+        assert!(hex.is_invalid(0xababu64, Exactly(2)));
+        assert!(!hex.is_invalid(0xabacu64, AtLeast(2)));
+        let binary = Radix::new(2).unwrap();
+        assert!(binary.is_invalid(0b1010u64, Exactly(2)));
+        assert!(binary.is_invalid(0b111u64, AtLeast(2)));
+        assert!(Radix::new(1).is_err());
+        assert!(Radix::new(37).is_err());
+    }
+
+    static ALL_KINDS: [RepeatRule; 8] = [
+        Exactly(2),
+        Exactly(3),
+        AtLeast(2),
+        AtLeast(3),
+        Between(2, 3),
+        Between(3, 5),
+        Palindrome,
+        RotationInvariant,
+    ];
+
+    #[test]
+    fn test_rules() {
+        assert!(DECIMAL.is_invalid(111111u64, Exactly(3)));
+        assert!(DECIMAL.is_invalid(121212u64, Exactly(3)));
+        assert!(!DECIMAL.is_invalid(123123u64, Exactly(3)));
+        assert!(DECIMAL.is_invalid(123123u64, Between(2, 3)));
+        assert!(!DECIMAL.is_invalid(123123u64, Between(3, 5)));
+        assert!(DECIMAL.is_invalid(12321u64, Palindrome));
+        assert!(DECIMAL.is_invalid(1221u64, Palindrome));
+        assert!(!DECIMAL.is_invalid(1231u64, Palindrome));
+        assert!(!DECIMAL.is_invalid(5u64, Palindrome));
+        assert_eq!(DECIMAL.next_invalid(999u64, Palindrome), Some(1001));
+        assert_eq!(DECIMAL.next_invalid(12345u64, Palindrome), Some(12421));
+        assert_eq!(DECIMAL.next_invalid(99u64, Exactly(3)), Some(111));
+        assert_eq!(DECIMAL.next_invalid(999u64, Exactly(3)), Some(101010));
+        let r: Range = Range {
+            first: 1000,
+            last: 1200,
+        };
+        let palindromes: Vec<u64> = InvalidIds::new(&r, Palindrome).collect();
+        assert_eq!(palindromes, vec![1001, 1111]);
+    }
+
+    #[test]
+    fn test_other_radixes_match_predicate() {
+        for radix in [2, 3, 7, 10, 16, 36] {
+            let radix = Radix::new(radix).unwrap();
+            let r: Range = Range {
+                first: 0u64,
+                last: 5_000,
+            };
+            for rule in ALL_KINDS {
+                let ids: Vec<u64> = InvalidIds::with_radix(&r, radix, rule).collect();
+                let expected: Vec<u64> =
+                    (0..=5_000).filter(|&v| radix.is_invalid(v, rule)).collect();
+                assert_eq!(ids, expected);
+                assert_eq!(InvalidIds::with_radix(&r, radix, rule).count(), ids.len());
+                let sum: u128 = ids.iter().map(|&i| i as u128).sum();
+                assert_eq!(InvalidIds::with_radix(&r, radix, rule).total(), Some(sum));
+            }
+        }
+    }
+
+    #[test]
+    fn test_u128_ranges() {
+        let ranges: Vec<Range<u128>> =
+            input_to_ranges("1234567890112345678900-1234567890112345678999").unwrap();
+        let ids: Vec<u128> = InvalidIds::new(&ranges[0], Exactly(2)).collect();
+        assert_eq!(ids, vec![1234567890112345678901]);
+        assert_eq!(
+            sum_invalid(&ranges, AtLeast(2), true).unwrap(),
+            1234567890112345678901
+        );
+
+        let to_max = Range {
+            first: u128::MAX - 1_000_000,
+            last: u128::MAX,
+        };
+        assert_eq!(InvalidIds::new(&to_max, AtLeast(2)).next(), None);
+        let everything = Range {
+            first: 0,
+            last: u128::MAX,
+        };
+        assert_eq!(InvalidIds::new(&everything, AtLeast(2)).total(), None);
     }
 }
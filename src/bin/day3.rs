@@ -1,61 +1,357 @@
-use aoc2025::get_input_string;
-
-fn get_batteries(desc: &str) -> Vec<Vec<u8>> {
-    let mut batteries = Vec::new();
-    for line in desc.trim().split_whitespace() {
-        let battery: Vec<u8> = line
-            .chars()
-            .filter_map(|ch| ch.to_digit(10).map(|n| n as u8))
-            .collect();
+use anyhow::{Result, bail};
+use aoc2025::{get_input_string, has_flag, init_logging, map_items, run_compared};
+use num_bigint::BigUint;
+use std::fmt;
+
+/// How battery banks are written: the characters for each cell value, in
+/// increasing order, and whether every bank must have the same length.
+struct BankFormat {
+    alphabet: Vec<char>,
+    ignore_case: bool,
+    equal_lengths: bool,
+}
+
+impl BankFormat {
+    fn decimal() -> Self {
+        Self::with_alphabet("0123456789").unwrap()
+    }
+
+    #[cfg(test)]
+    fn hex() -> Self {
+        let mut format = Self::with_alphabet("0123456789abcdef").unwrap();
+        format.ignore_case = true;
+        format
+    }
+
+    /// Cells are written with the characters of `alphabet`, the first being worth 0.
+    fn with_alphabet(alphabet: &str) -> Result<Self> {
+        let alphabet: Vec<char> = alphabet.chars().collect();
+        if !(2..=36).contains(&alphabet.len()) {
+            bail!("Alphabet {:?} must have 2 to 36 characters", alphabet);
+        }
+        Ok(Self {
+            alphabet,
+            ignore_case: false,
+            equal_lengths: false,
+        })
+    }
+
+    fn equal_lengths(mut self) -> Self {
+        self.equal_lengths = true;
+        self
+    }
+
+    fn radix(&self) -> u32 {
+        self.alphabet.len() as u32
+    }
+
+    fn cell_value(&self, ch: char) -> Option<u8> {
+        let ch = if self.ignore_case {
+            ch.to_ascii_lowercase()
+        } else {
+            ch
+        };
+        self.alphabet.iter().position(|&c| c == ch).map(|v| v as u8)
+    }
+}
+
+/// Reads one bank per non-blank line. Line and column numbers in errors count from 1.
+fn get_batteries(desc: &str, format: &BankFormat) -> Result<Vec<Vec<u8>>> {
+    let mut batteries: Vec<Vec<u8>> = Vec::new();
+    for (line_no, line) in desc.lines().enumerate() {
+        let indent = line.len() - line.trim_start().len();
+        let mut battery = Vec::new();
+        for (col, ch) in line.trim().chars().enumerate() {
+            let Some(cell) = format.cell_value(ch) else {
+                bail!(
+                    "Line {}, column {}: unexpected character {:?}",
+                    line_no + 1,
+                    indent + col + 1,
+                    ch
+                );
+            };
+            battery.push(cell);
+        }
+        if battery.is_empty() {
+            continue;
+        }
+        if let Some(first) = batteries.first()
+            && format.equal_lengths
+            && battery.len() != first.len()
+        {
+            bail!(
+                "Line {}: bank has {} cells, but the first has {}",
+                line_no + 1,
+                battery.len(),
+                first.len()
+            );
+        }
         batteries.push(battery);
     }
-    batteries
+    Ok(batteries)
 }
 
-fn largest_cell(batt: &[u8]) -> Option<u8> {
-    batt.iter().max().copied()
+/// The cells chosen from a battery bank, in bank order, and their positions.
+/// Together the cell digits spell out the joltage, however many there are.
+#[derive(Debug, PartialEq, Eq)]
+struct Selection {
+    indices: Vec<usize>,
+    cells: Vec<u8>,
 }
 
-fn first_position(batt: &[u8], value: u8) -> Option<usize> {
-    batt.iter().position(|v| *v == value)
+impl Selection {
+    fn from_indices(batt: &[u8], indices: Vec<usize>) -> Self {
+        let cells = indices.iter().map(|&i| batt[i]).collect();
+        Self { indices, cells }
+    }
+
+    /// The joltage of cells from a bank in the given radix, exact however many cells there are.
+    fn value_in(&self, radix: u32) -> BigUint {
+        BigUint::from_radix_be(&self.cells, radix).expect("Cell values are below the radix")
+    }
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &c in &self.cells {
+            write!(f, "{}", char::from_digit(c as u32, 36).unwrap_or('?'))?;
+        }
+        Ok(())
+    }
 }
 
-fn best_joltage<const NUM_CELLS: usize>(batt: &[u8]) -> u64 {
-    assert!(batt.len() > NUM_CELLS);
-    // println!("Batt: {:?}", batt);
-    let mut cells: [u8; NUM_CELLS] = [0; NUM_CELLS];
+/// Picks `num_cells` cells, keeping their order, to make the largest joltage.
+/// Returns None if the bank has fewer cells than that.
+fn best_selection(batt: &[u8], num_cells: usize) -> Option<Selection> {
+    stack_selection(batt, num_cells, |top, cell| top < cell)
+}
+
+/// Like `best_selection`, but for the smallest joltage.
+fn min_selection(batt: &[u8], num_cells: usize) -> Option<Selection> {
+    stack_selection(batt, num_cells, |top, cell| top > cell)
+}
+
+/// A cell is dropped from the stack whenever `beaten_by(top, cell)` and there
+/// are still enough cells left to fill the selection, so each cell is pushed
+/// and popped at most once.
+fn stack_selection(
+    batt: &[u8],
+    num_cells: usize,
+    beaten_by: impl Fn(u8, u8) -> bool,
+) -> Option<Selection> {
+    if num_cells > batt.len() {
+        return None;
+    }
+    let mut stack: Vec<usize> = Vec::with_capacity(num_cells);
+    for (i, &cell) in batt.iter().enumerate() {
+        let remaining = batt.len() - i;
+        while let Some(&top) = stack.last() {
+            if beaten_by(batt[top], cell) && stack.len() - 1 + remaining >= num_cells {
+                stack.pop();
+            } else {
+                break;
+            }
+        }
+        if stack.len() < num_cells {
+            stack.push(i);
+        }
+    }
+    Some(Selection::from_indices(batt, stack))
+}
+
+/// Picks the `rank`th largest distinct joltage (1 is the best) that
+/// `num_cells` cells can make, or None if there aren't that many.
+///
+/// `num_distinct[i][len]` counts the distinct joltages of `len` cells within
+/// `batt[i..]`. Each leading value is tried from largest to smallest, skipping
+/// past whole groups of joltages until the one holding `rank` is found.
+fn ranked_selection(batt: &[u8], num_cells: usize, rank: u128) -> Option<Selection> {
+    let n = batt.len();
+    let num_values = batt.iter().max().map_or(0, |&m| m as usize + 1);
+    // next_at[i][v] is the first position at or after i holding value v.
+    let mut next_at = vec![vec![None; num_values]; n + 1];
+    for i in (0..n).rev() {
+        next_at[i] = next_at[i + 1].clone();
+        next_at[i][batt[i] as usize] = Some(i);
+    }
+    let mut num_distinct = vec![vec![0u128; num_cells + 1]; n + 1];
+    for i in (0..=n).rev() {
+        num_distinct[i][0] = 1;
+        for len in 1..=num_cells {
+            num_distinct[i][len] = next_at[i].iter().flatten().fold(0u128, |count, &at| {
+                count.saturating_add(num_distinct[at + 1][len - 1])
+            });
+        }
+    }
+    if rank == 0 || num_distinct[0][num_cells] < rank {
+        return None;
+    }
+    let mut rank = rank;
+    let mut indices = Vec::with_capacity(num_cells);
     let mut start = 0;
-    for icell in 0..NUM_CELLS {
-        let end = batt.len() - NUM_CELLS + icell + 1;
-        let cell_val = largest_cell(&batt[start..end]).unwrap();
-        // print!("  cell {} chose between {}..{}", icell, start, end);
-        start = first_position(&batt[start..end], cell_val).unwrap() + start + 1;
-        cells[icell] = cell_val;
-        // println!(" and found {} at {}", cell_val, start - 1);
+    for len in (1..=num_cells).rev() {
+        for &at in next_at[start].iter().rev().flatten() {
+            let count = num_distinct[at + 1][len - 1];
+            if rank <= count {
+                indices.push(at);
+                start = at + 1;
+                break;
+            }
+            rank -= count;
+        }
+    }
+    Some(Selection::from_indices(batt, indices))
+}
+
+/// Limits on how many cells are skipped between one chosen cell and the next.
+#[derive(Debug, Clone, Copy)]
+struct Spacing {
+    min_skip: usize,
+    max_skip: Option<usize>,
+}
+
+impl Spacing {
+    const NOT_ADJACENT: Spacing = Spacing {
+        min_skip: 1,
+        max_skip: None,
+    };
+
+    fn skip_at_most(max_skip: usize) -> Self {
+        Self {
+            min_skip: 0,
+            max_skip: Some(max_skip),
+        }
+    }
+
+    /// Positions the pick after one at `i` may be at, in a bank of `len` cells.
+    fn next_picks(&self, i: usize, len: usize) -> std::ops::Range<usize> {
+        let end = self.max_skip.map_or(len, |m| (i + m + 2).min(len));
+        (i + self.min_skip + 1).min(end)..end
+    }
+}
+
+/// Picks `num_cells` cells to make the largest joltage while keeping to
+/// `spacing`. Returns None if no choice of cells fits.
+///
+/// The greedy stack no longer works, since an early pick can rule out later
+/// ones. Instead `fits[len][i]` records whether `len + 1` cells can be chosen
+/// starting at i. The best value is then chosen one cell at a time, keeping
+/// every position that ties for the best so far.
+fn best_spaced_selection(batt: &[u8], num_cells: usize, spacing: Spacing) -> Option<Selection> {
+    let n = batt.len();
+    if num_cells == 0 {
+        return Some(Selection::from_indices(batt, Vec::new()));
     }
-    let mut jolts = 0;
-    for &c in cells.iter() {
-        jolts = jolts * 10 + c as u64;
+    let mut fits = vec![vec![true; n]];
+    for len in 1..num_cells {
+        let row = (0..n)
+            .map(|i| spacing.next_picks(i, n).any(|j| fits[len - 1][j]))
+            .collect();
+        fits.push(row);
+    }
+
+    let best_of = |positions: Vec<usize>| -> Vec<usize> {
+        let best = positions.iter().map(|&i| batt[i]).max();
+        positions
+            .into_iter()
+            .filter(|&i| Some(batt[i]) == best)
+            .collect()
+    };
+    let mut tied = best_of((0..n).filter(|&i| fits[num_cells - 1][i]).collect());
+    if tied.is_empty() {
+        return None;
+    }
+    // came_from[step][j] is the earliest tied position at the previous step leading to j.
+    let mut came_from = Vec::with_capacity(num_cells - 1);
+    for remaining in (1..num_cells).rev() {
+        let mut from = vec![None; n];
+        for &i in &tied {
+            for j in spacing.next_picks(i, n) {
+                if fits[remaining - 1][j] && from[j].is_none() {
+                    from[j] = Some(i);
+                }
+            }
+        }
+        tied = best_of((0..n).filter(|&j| from[j].is_some()).collect());
+        came_from.push(from);
+    }
+
+    let mut indices = vec![tied[0]];
+    for from in came_from.iter().rev() {
+        indices.push(from[*indices.last().unwrap()].unwrap());
     }
-    jolts
+    indices.reverse();
+    Some(Selection::from_indices(batt, indices))
 }
 
-fn main() {
-    let input = get_input_string("input_d3.txt").unwrap();
-    let bats = get_batteries(&input);
+fn best_joltage(batt: &[u8], num_cells: usize, format: &BankFormat) -> BigUint {
+    best_selection(batt, num_cells)
+        .expect("Battery bank is too small")
+        .value_in(format.radix())
+}
+
+fn main() -> Result<()> {
+    init_logging();
+    let input = get_input_string("input_d3.txt")?;
+    let format = BankFormat::decimal().equal_lengths();
+    let bats = get_batteries(&input, &format)?;
     {
-        let jolts_sum: u64 = bats.iter().map(|b| best_joltage::<2>(b)).sum();
+        let jolts_sum = run_compared("Day 3 pt1", |parallel| {
+            map_items(&bats, parallel, |b| best_joltage(b, 2, &format))
+                .into_iter()
+                .sum::<BigUint>()
+        });
         println!("Day 3 pt1: ttl jolts = {}", jolts_sum);
     }
     {
-        let jolts_sum: u64 = bats.iter().map(|b| best_joltage::<12>(b)).sum();
+        let jolts_sum = run_compared("Day 3 pt2", |parallel| {
+            map_items(&bats, parallel, |b| best_joltage(b, 12, &format))
+                .into_iter()
+                .sum::<BigUint>()
+        });
         println!("Day 3 pt2: ttl jolts = {}", jolts_sum);
     }
+    // Other ways of picking 12 cells, with `--variants`.
+    if has_flag("--variants") {
+        let value = |sel: Option<Selection>| sel.map(|s| s.value_in(format.radix()));
+        let sums = run_compared("Day 3 variants", |parallel| {
+            let per_bank = map_items(&bats, parallel, |b| {
+                [
+                    value(min_selection(b, 12)),
+                    value(ranked_selection(b, 12, 2)),
+                    value(best_spaced_selection(b, 12, Spacing::NOT_ADJACENT)),
+                    value(best_spaced_selection(b, 12, Spacing::skip_at_most(1))),
+                ]
+            });
+            // A total is None if any bank can't make its selection.
+            per_bank.into_iter().fold(
+                std::array::from_fn(|_| Some(BigUint::ZERO)),
+                |sums: [Option<BigUint>; 4], values| {
+                    let mut values = values.into_iter();
+                    sums.map(|sum| Some(sum? + values.next().unwrap()?))
+                },
+            )
+        });
+        let names = ["min", "second best", "no adjacent cells", "skip at most 1"];
+        for (name, sum) in names.iter().zip(sums) {
+            match sum {
+                Some(sum) => println!("Day 3 {name}: ttl jolts = {sum}"),
+                None => println!("Day 3 {name}: some banks have no such selection"),
+            }
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aoc2025::Lcg;
+
+    /// A decimal selection's joltage, small enough for a u128.
+    fn decimal(sel: &Selection) -> u128 {
+        u128::try_from(sel.value_in(10)).unwrap()
+    }
 
     static INPUT: &str = r#"
 987654321111111
@@ -71,46 +367,203 @@ mod tests {
 5678a
 90
         "#;
-        let bats = get_batteries(SOME_BATTS);
-        assert_eq!(bats, vec![vec![1, 2, 3, 4], vec![5, 6, 7, 8], vec![9, 0]]);
+        let err = get_batteries(SOME_BATTS, &BankFormat::decimal()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Line 3, column 5: unexpected character 'a'"
+        );
+        let bats = get_batteries(SOME_BATTS, &BankFormat::hex()).unwrap();
+        assert_eq!(
+            bats,
+            vec![vec![1, 2, 3, 4], vec![5, 6, 7, 8, 10], vec![9, 0]]
+        );
+        let err = get_batteries(SOME_BATTS, &BankFormat::hex().equal_lengths()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Line 3: bank has 5 cells, but the first has 4"
+        );
+    }
+
+    #[test]
+    fn test_other_alphabets() {
+        let hex = BankFormat::hex();
+        let bats = get_batteries("f0A9\n  0b1c", &hex).unwrap();
+        let sel = best_selection(&bats[0], 2).unwrap();
+        assert_eq!(sel.to_string(), "fa");
+        assert_eq!(sel.value_in(hex.radix()), BigUint::from(0xfau32));
+        assert_eq!(best_joltage(&bats[0], 2, &hex), BigUint::from(0xfau32));
+        assert_eq!(bats[1], vec![0, 11, 1, 12]);
+        let err = get_batteries("  0x1", &hex).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Line 1, column 4: unexpected character 'x'"
+        );
+
+        let dice = BankFormat::with_alphabet("⚀⚁⚂⚃⚄⚅").unwrap();
+        let bats = get_batteries("⚂⚅⚀⚄", &dice).unwrap();
+        assert_eq!(bats, vec![vec![2, 5, 0, 4]]);
+        assert!(BankFormat::with_alphabet("x").is_err());
     }
 
     #[test]
     fn test_pt1() {
-        let bats = get_batteries(INPUT);
-        let besties: Vec<u64> = bats.iter().map(|b| best_joltage::<2>(b)).collect();
-        assert_eq!(besties, vec![98, 89, 78, 92]);
-        let jolts_sum: u64 = besties.into_iter().sum();
-        assert_eq!(jolts_sum, 357);
+        let decimal = BankFormat::decimal();
+        let bats = get_batteries(INPUT, &decimal).unwrap();
+        let besties: Vec<BigUint> = bats.iter().map(|b| best_joltage(b, 2, &decimal)).collect();
+        assert_eq!(besties, [98u32, 89, 78, 92].map(BigUint::from));
+        let jolts_sum: BigUint = besties.into_iter().sum();
+        assert_eq!(jolts_sum, BigUint::from(357u32));
     }
 
     #[test]
     fn test_input_length() {
         let input = get_input_string("input_d3.txt").unwrap();
-        let bats = get_batteries(&input);
+        let bats = get_batteries(&input, &BankFormat::decimal().equal_lengths()).unwrap();
         assert_eq!(bats.len(), 200);
-        for bat in &bats {
-            assert_eq!(bat.len(), 100);
-        }
+        assert_eq!(bats[0].len(), 100);
     }
 
     #[test]
     fn test_best_joltage() {
-        assert_eq!(best_joltage::<2>(&vec![3, 3, 3, 9, 1]), 91);
-        assert_eq!(best_joltage::<2>(&vec![3, 3, 3, 3, 9]), 39);
-        assert_eq!(best_joltage::<2>(&vec![4, 3, 3, 3, 9]), 49);
-        assert_eq!(best_joltage::<2>(&vec![8, 7, 6, 5, 9]), 89);
+        let decimal = BankFormat::decimal();
+        assert_eq!(
+            best_joltage(&[3, 3, 3, 9, 1], 2, &decimal),
+            BigUint::from(91u32)
+        );
+        assert_eq!(
+            best_joltage(&[3, 3, 3, 3, 9], 2, &decimal),
+            BigUint::from(39u32)
+        );
+        assert_eq!(
+            best_joltage(&[4, 3, 3, 3, 9], 2, &decimal),
+            BigUint::from(49u32)
+        );
+        assert_eq!(
+            best_joltage(&[8, 7, 6, 5, 9], 2, &decimal),
+            BigUint::from(89u32)
+        );
     }
 
     #[test]
     fn test_pt2() {
-        let bats = get_batteries(INPUT);
-        let besties: Vec<u64> = bats.iter().map(|b| best_joltage::<12>(b)).collect();
+        let decimal = BankFormat::decimal();
+        let bats = get_batteries(INPUT, &decimal).unwrap();
+        let besties: Vec<BigUint> = bats.iter().map(|b| best_joltage(b, 12, &decimal)).collect();
         assert_eq!(
             besties,
-            vec![987654321111, 811111111119, 434234234278, 888911112111]
+            [987654321111u64, 811111111119, 434234234278, 888911112111].map(BigUint::from)
         );
-        let jolts_sum: u64 = besties.into_iter().sum();
-        assert_eq!(jolts_sum, 3121910778619);
+        let jolts_sum: BigUint = besties.into_iter().sum();
+        assert_eq!(jolts_sum, BigUint::from(3121910778619u64));
+    }
+
+    #[test]
+    fn test_selection_indices() {
+        let sel = best_selection(&[8, 1, 8, 1, 9, 1], 3).unwrap();
+        assert_eq!(sel.indices, vec![0, 4, 5]);
+        assert_eq!(decimal(&sel), 891);
+        assert_eq!(best_selection(&[1, 2], 3), None);
+        let all = best_selection(&[3, 1, 2], 3).unwrap();
+        assert_eq!(all.indices, vec![0, 1, 2]);
+        assert_eq!(decimal(&all), 312);
+    }
+
+    /// All choices of `num_cells` cells keeping to `spacing`, by brute force.
+    fn all_selections(batt: &[u8], num_cells: usize, spacing: Spacing) -> Vec<Selection> {
+        (0u32..1 << batt.len())
+            .filter(|mask| mask.count_ones() as usize == num_cells)
+            .map(|mask| {
+                (0..batt.len())
+                    .filter(|i| mask & (1 << i) != 0)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|indices| {
+                indices
+                    .windows(2)
+                    .all(|w| spacing.next_picks(w[0], batt.len()).contains(&w[1]))
+            })
+            .map(|indices| Selection::from_indices(batt, indices))
+            .collect()
+    }
+
+    /// Small pseudo-random banks, the same every run.
+    fn test_banks() -> Vec<Vec<u8>> {
+        let mut rng = Lcg::new(12345);
+        (0..200)
+            .map(|b| {
+                (0..4 + b % 7)
+                    .map(|_| rng.below(if b % 2 == 0 { 3 } else { 10 }) as u8)
+                    .collect()
+            })
+            .collect()
+    }
+
+    const ANY_SPACING: Spacing = Spacing {
+        min_skip: 0,
+        max_skip: None,
+    };
+
+    #[test]
+    fn test_variants_against_brute_force() {
+        for batt in test_banks() {
+            for num_cells in 1..=batt.len() {
+                let all = all_selections(&batt, num_cells, ANY_SPACING);
+                let values: std::collections::BTreeSet<u128> = all.iter().map(decimal).collect();
+                let best = best_selection(&batt, num_cells).unwrap();
+                assert_eq!(Some(decimal(&best)), values.last().copied());
+                let min = min_selection(&batt, num_cells).unwrap();
+                assert_eq!(Some(decimal(&min)), values.first().copied());
+                for (rank, &expected) in values.iter().rev().enumerate() {
+                    let ranked = ranked_selection(&batt, num_cells, rank as u128 + 1).unwrap();
+                    assert_eq!(decimal(&ranked), expected);
+                }
+                assert!(ranked_selection(&batt, num_cells, values.len() as u128 + 1).is_none());
+
+                for spacing in [
+                    ANY_SPACING,
+                    Spacing::NOT_ADJACENT,
+                    Spacing::skip_at_most(0),
+                    Spacing::skip_at_most(2),
+                ] {
+                    let spaced = all_selections(&batt, num_cells, spacing);
+                    let expected = spaced.iter().map(decimal).max();
+                    let found = best_spaced_selection(&batt, num_cells, spacing);
+                    assert_eq!(found.as_ref().map(decimal), expected);
+                    if let Some(found) = found {
+                        assert!(spaced.iter().any(|s| s.indices == found.indices));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_spaced_selection() {
+        let batt = [9, 9, 1, 8, 7];
+        let sel = best_spaced_selection(&batt, 3, Spacing::NOT_ADJACENT).unwrap();
+        assert_eq!(sel.indices, vec![0, 2, 4]);
+        assert_eq!(decimal(&sel), 917);
+        let sel = best_spaced_selection(&batt, 2, Spacing::skip_at_most(1)).unwrap();
+        assert_eq!(decimal(&sel), 99);
+        assert!(best_spaced_selection(&batt, 4, Spacing::NOT_ADJACENT).is_none());
+        let same = best_spaced_selection(&batt, 3, ANY_SPACING).unwrap();
+        assert_eq!(same, best_selection(&batt, 3).unwrap());
+    }
+
+    #[test]
+    fn test_long_selections() {
+        let input = get_input_string("input_d3.txt").unwrap();
+        let bats = get_batteries(&input, &BankFormat::decimal()).unwrap();
+        let sel = best_selection(&bats[0], 30).unwrap();
+        assert_eq!(sel.to_string().len(), 30);
+        assert_eq!(sel.value_in(10).to_string(), sel.to_string());
+        let whole = best_selection(&bats[0], bats[0].len()).unwrap();
+        assert_eq!(whole.cells, bats[0]);
+        assert_eq!(whole.value_in(10).to_string(), whole.to_string());
+
+        let hex = BankFormat::hex();
+        let bank = get_batteries(&"f".repeat(40), &hex).unwrap();
+        let jolts = best_joltage(&bank[0], 40, &hex);
+        assert_eq!(jolts, (BigUint::from(1u32) << 160) - 1u32);
     }
 }
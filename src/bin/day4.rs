@@ -1,114 +1,1217 @@
 use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fmt,
     fs::File,
-    io::{BufRead as _, BufReader, Read},
+    hash::Hash,
+    io::{BufRead, BufReader, BufWriter, Read},
+    path::Path,
+    str::FromStr,
 };
 
-use aoc2025::get_input;
-use ndarray::{Array, Array1, Array2, s};
+use anyhow::{Context as _, Result, anyhow, bail, ensure};
+use aoc2025::frames::{Frame, Rgb, write_gif, write_ppm};
+use aoc2025::{flag_value, get_input, has_flag, init_logging};
+use ndarray::{Array, Array1, Array2, ArrayD, ArrayView2, Dimension as _, IxDyn, s};
 
-fn read_map<R: Read>(rdr: &mut R) -> Array2<u8> {
-    let mut rows: Vec<Vec<u8>> = Vec::new();
-    let lrdr = BufReader::new(rdr);
-    for line in lrdr.lines() {
-        let line = line.unwrap();
-        let sline = line.trim();
-        let row: Vec<u8> = sline
+/// Why a map couldn't be read. Line and column numbers count from 1.
+#[derive(Debug)]
+enum MapError {
+    Io(std::io::Error),
+    /// A row's width differs from the first row's, in strict mode.
+    Ragged {
+        line: usize,
+        width: usize,
+        expected: usize,
+    },
+    UnknownGlyph {
+        line: usize,
+        column: usize,
+        glyph: char,
+    },
+    Empty,
+    /// The slices of a grid don't fill a box of this shape.
+    UnevenSlices {
+        shape: Vec<usize>,
+    },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "Couldn't read the map: {e}"),
+            MapError::Ragged {
+                line,
+                width,
+                expected,
+            } => write!(
+                f,
+                "Line {line}: row has {width} cells, but expected {expected}"
+            ),
+            MapError::UnknownGlyph {
+                line,
+                column,
+                glyph,
+            } => write!(f, "Line {line}, column {column}: unknown glyph {glyph:?}"),
+            MapError::Empty => write!(f, "The map has no rows"),
+            MapError::UnevenSlices { shape } => write!(
+                f,
+                "The slices don't all have the same shape, so can't fill a {shape:?} grid"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MapError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A row that lenient reading padded out to the width of the widest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PaddedRow {
+    line: usize,
+    width: usize,
+    padded_to: usize,
+}
+
+impl fmt::Display for PaddedRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Line {}: padded row of {} cells to {}",
+            self.line, self.width, self.padded_to
+        )
+    }
+}
+
+/// How a bale map is written: which glyphs mean a bale or an empty cell, and
+/// whether rows of different widths are an error or padded with empty cells.
+struct MapFormat {
+    legend: HashMap<char, u8>,
+    lenient: bool,
+}
+
+impl MapFormat {
+    /// The puzzle's format, where `@` is a bale, `.` is empty, and every row is the same width.
+    fn strict() -> Self {
+        MapFormat {
+            legend: HashMap::from([('.', 0), ('@', 1)]),
+            lenient: false,
+        }
+    }
+
+    fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    /// Adds glyphs to the legend from a spec such as `#=@,o=.`, which reads
+    /// `#` as a bale and `o` as an empty cell.
+    fn with_legend(mut self, spec: &str) -> Result<Self> {
+        for entry in spec.split(',') {
+            let glyphs: Vec<char> = entry.trim().chars().collect();
+            let cell = match glyphs.as_slice() {
+                [_, '=', '.'] => 0,
+                [_, '=', '@'] => 1,
+                _ => bail!("Legend entry {entry:?} should look like '#=@' or 'o=.'"),
+            };
+            self.legend.insert(glyphs[0], cell);
+        }
+        Ok(self)
+    }
+
+    /// Reads a map, embedded in a border of empty cells, along with the rows
+    /// lenient reading had to pad. Blank lines are skipped.
+    fn read<R: Read>(&self, rdr: &mut R) -> Result<(Array2<u8>, Vec<PaddedRow>), MapError> {
+        let mut lines = self.rows(BufReader::new(rdr));
+        let mut rows = Vec::new();
+        for row in &mut lines {
+            let (_, row) = row?;
+            if !row.is_empty() {
+                rows.push(row);
+            }
+        }
+        let inner_width = lines.width().ok_or(MapError::Empty)?;
+
+        // Embed this input into an array with an extra border of 1
+        let width = inner_width + 2;
+        let height = rows.len() + 2;
+        let mut map = Array::zeros((height, width));
+        for (i, row) in rows.into_iter().enumerate() {
+            map.slice_mut(s![i + 1, 1..row.len() + 1])
+                .assign(&Array1::from(row));
+        }
+        Ok((map, lines.padded()))
+    }
+
+    /// Decodes the lines of a map one at a time.
+    fn rows<R: BufRead>(&self, rdr: R) -> MapRows<'_, R> {
+        MapRows {
+            format: self,
+            rdr,
+            buf: String::new(),
+            line: 0,
+            widths: Vec::new(),
+        }
+    }
+}
+
+/// The rows of a map as a `MapFormat` reads them, with their line numbers.
+///
+/// Blank lines come through as empty rows, since they separate the slices of a
+/// grid. In strict mode, a row of a different width from the first is an error.
+struct MapRows<'a, R> {
+    format: &'a MapFormat,
+    rdr: R,
+    buf: String,
+    line: usize,
+    /// The line number and width of each row that isn't blank.
+    widths: Vec<(usize, usize)>,
+}
+
+impl<R: BufRead> Iterator for MapRows<'_, R> {
+    type Item = Result<(usize, Vec<u8>), MapError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buf.clear();
+        match self.rdr.read_line(&mut self.buf) {
+            Ok(0) => None,
+            Ok(_) => {
+                self.line += 1;
+                Some(self.decode().map(|row| (self.line, row)))
+            }
+            Err(e) => Some(Err(MapError::Io(e))),
+        }
+    }
+}
+
+impl<R> MapRows<'_, R> {
+    fn decode(&mut self) -> Result<Vec<u8>, MapError> {
+        let indent = self.buf.len() - self.buf.trim_start().len();
+        let row: Vec<u8> = self
+            .buf
+            .trim()
             .chars()
-            .filter_map(|ch| match ch {
-                '.' => Some(0),
-                '@' => Some(1),
-                _ => None,
+            .enumerate()
+            .map(|(col, glyph)| {
+                self.format
+                    .legend
+                    .get(&glyph)
+                    .copied()
+                    .ok_or(MapError::UnknownGlyph {
+                        line: self.line,
+                        column: indent + col + 1,
+                        glyph,
+                    })
             })
-            .collect();
-        if rows.len() > 0 && row.len() != rows[0].len() {
-            println!(
-                "Error: Line {} has a different length than the first.",
-                sline
-            );
-        } else {
-            rows.push(row);
+            .collect::<Result<_, _>>()?;
+        if row.is_empty() {
+            return Ok(row);
+        }
+        if let Some(&(_, expected)) = self.widths.first()
+            && !self.format.lenient
+            && row.len() != expected
+        {
+            return Err(MapError::Ragged {
+                line: self.line,
+                width: row.len(),
+                expected,
+            });
         }
+        self.widths.push((self.line, row.len()));
+        Ok(row)
     }
-    // Embed this input into an array with an extra border of 1
-    let width = rows[0].len() + 2;
-    let height = rows.len() + 2;
-    let mut map = Array::zeros((height, width));
-    for (i, row) in rows.into_iter().enumerate() {
-        map.slice_mut(s![i + 1, 1..width - 1])
-            .assign(&Array1::from(row));
+
+    /// The width of the widest row so far, if there has been one.
+    fn width(&self) -> Option<usize> {
+        self.widths.iter().map(|&(_, width)| width).max()
+    }
+
+    /// The rows narrower than the widest, which lenient reading pads out.
+    fn padded(&self) -> Vec<PaddedRow> {
+        let padded_to = self.width().unwrap_or(0);
+        self.widths
+            .iter()
+            .filter(|&&(_, width)| width < padded_to)
+            .map(|&(line, width)| PaddedRow {
+                line,
+                width,
+                padded_to,
+            })
+            .collect()
+    }
+}
+
+/// The shape of the neighbourhood around a bale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stencil {
+    /// The four orthogonal neighbours.
+    VonNeumann,
+    /// The eight surrounding cells.
+    Moore,
+    /// Every cell within a square of the given radius, so `Radius(1)` is `Moore`.
+    Radius(usize),
+    /// The six neighbours of a hexagonal map, with odd rows shifted half a
+    /// cell to the right.
+    Hex,
+}
+
+impl Stencil {
+    /// Offsets from a cell in an even or odd row to its neighbours.
+    fn offsets(self, odd_row: bool) -> Vec<(isize, isize)> {
+        let square = |r: isize| {
+            (-r..=r)
+                .flat_map(move |dr| (-r..=r).map(move |dc| (dr, dc)))
+                .filter(|&off| off != (0, 0))
+                .collect()
+        };
+        match self {
+            Stencil::VonNeumann => vec![(-1, 0), (0, -1), (0, 1), (1, 0)],
+            Stencil::Moore => square(1),
+            Stencil::Radius(r) => square(r as isize),
+            Stencil::Hex if odd_row => vec![(-1, 0), (-1, 1), (0, -1), (0, 1), (1, 0), (1, 1)],
+            Stencil::Hex => vec![(-1, -1), (-1, 0), (0, -1), (0, 1), (1, -1), (1, 0)],
+        }
     }
-    map
 }
 
-fn count_neighbors(map: &Array2<u8>, row: usize, col: usize) -> u8 {
-    let region = map.slice(s![row - 1..row + 2, col - 1..col + 2]);
-    if region.shape()[0] != 3 || region.shape()[1] != 3 {
-        println!("Error: region shape is {:?}", region.shape());
-        panic!("Bad index into map");
+/// How a neighbour count is compared with the rule's threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Less,
+    AtMost,
+    Equal,
+    AtLeast,
+    More,
+}
+
+impl Comparison {
+    fn holds(self, count: usize, threshold: usize) -> bool {
+        match self {
+            Comparison::Less => count < threshold,
+            Comparison::AtMost => count <= threshold,
+            Comparison::Equal => count == threshold,
+            Comparison::AtLeast => count >= threshold,
+            Comparison::More => count > threshold,
+        }
     }
-    let ttl_count: u8 = region.iter().sum();
-    ttl_count - region[[1, 1]]
 }
 
-fn num_accessable_bales(map: &Array2<u8>) -> usize {
-    let mut count = 0;
-    let rows = map.shape()[0];
-    let cols = map.shape()[1];
-    for irow in 1..rows - 1 {
-        for jcol in 1..cols - 1 {
-            if map[[irow, jcol]] == 1 && count_neighbors(&map, irow, jcol) < 4 {
-                count += 1;
+/// What a neighbourhood sees past the edge of the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Boundary {
+    /// Everything off the map is empty.
+    Empty,
+    /// The map wraps around, as on a torus.
+    Wrap,
+    /// The map is mirrored about its edge cells, which are not repeated.
+    Reflect,
+}
+
+impl Boundary {
+    /// Checks that a stencil reaching `reach` cells either way along an axis of
+    /// length `len` only ever lands on distinct cells other than the middle one,
+    /// which wrapping or reflecting a short axis wouldn't.
+    fn check_fits(self, reach: usize, len: usize) -> Result<()> {
+        let needed = match self {
+            Boundary::Empty => 0,
+            Boundary::Wrap => 2 * reach + 1,
+            Boundary::Reflect => reach + 1,
+        };
+        ensure!(
+            len >= needed,
+            "A {self:?} boundary needs at least {needed} cells along each axis for this stencil, not {len}"
+        );
+        Ok(())
+    }
+
+    /// For each index on an axis of length `len`, the indices up to `reach`
+    /// past either end that land on it.
+    fn sources(self, reach: usize, len: usize) -> Vec<Vec<isize>> {
+        let mut sources = vec![Vec::new(); len];
+        let reach = reach as isize;
+        for i in -reach..len as isize + reach {
+            if let Some(to) = self.resolve(i, len) {
+                sources[to].push(i);
             }
         }
+        sources
+    }
+
+    /// Where index `i` lands on an axis of length `len`, if anywhere.
+    fn resolve(self, i: isize, len: usize) -> Option<usize> {
+        let n = len as isize;
+        match self {
+            Boundary::Empty => (0..n).contains(&i).then_some(i as usize),
+            Boundary::Wrap => Some(i.rem_euclid(n) as usize),
+            Boundary::Reflect if n == 1 => Some(0),
+            Boundary::Reflect => {
+                let m = i.rem_euclid(2 * (n - 1));
+                Some(if m < n { m } else { 2 * (n - 1) - m } as usize)
+            }
+        }
+    }
+}
+
+/// Decides which bales a forklift can reach.
+///
+/// A bale is accessible when the number of bales in its `stencil` compares
+/// with `threshold` as `comparison` says. The default is the puzzle's rule:
+/// fewer than four bales among the eight surrounding cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rule {
+    stencil: Stencil,
+    comparison: Comparison,
+    threshold: usize,
+    boundary: Boundary,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::new(Stencil::Moore, Comparison::Less, 4)
+    }
+}
+
+impl Rule {
+    fn new(stencil: Stencil, comparison: Comparison, threshold: usize) -> Self {
+        Rule {
+            stencil,
+            comparison,
+            threshold,
+            boundary: Boundary::Empty,
+        }
+    }
+
+    fn with_boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    /// Whether a bale with `count` bales in its neighbourhood is accessible.
+    fn accepts(&self, count: usize) -> bool {
+        self.comparison.holds(count, self.threshold)
     }
-    count
 }
 
-fn remove_and_count(map: &mut Array2<u8>) -> usize {
-    let mut count = 0;
-    let rows = map.shape()[0];
-    let cols = map.shape()[1];
-    for irow in 1..rows - 1 {
-        for jcol in 1..cols - 1 {
-            if map[[irow, jcol]] == 1 && count_neighbors(&map, irow, jcol) < 4 {
-                count += 1;
-                map[[irow, jcol]] = 0;
+/// Parses a rule such as `moore,<4` or `hex,>=2,wrap`: a stencil (`von-neumann`,
+/// `moore`, `radiusN` or `hex`), a comparison and threshold, and optionally a
+/// boundary (`empty`, `wrap` or `reflect`).
+impl FromStr for Rule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        let [stencil, test, rest @ ..] = parts.as_slice() else {
+            bail!("Rule '{s}' needs a stencil and a threshold");
+        };
+        let stencil = match *stencil {
+            "von-neumann" => Stencil::VonNeumann,
+            "moore" => Stencil::Moore,
+            "hex" => Stencil::Hex,
+            other => match other.strip_prefix("radius").map(str::parse) {
+                Some(Ok(r)) if r > 0 => Stencil::Radius(r),
+                _ => bail!("Unknown stencil '{other}'"),
+            },
+        };
+        // Two character operators first, so "<=" isn't taken as "<".
+        let (comparison, threshold) = [
+            ("<=", Comparison::AtMost),
+            (">=", Comparison::AtLeast),
+            ("<", Comparison::Less),
+            (">", Comparison::More),
+            ("=", Comparison::Equal),
+        ]
+        .into_iter()
+        .find_map(|(op, cmp)| test.strip_prefix(op).map(|num| (cmp, num)))
+        .ok_or_else(|| anyhow!("Threshold '{test}' should start with <, <=, =, >= or >"))?;
+        let threshold = threshold
+            .trim()
+            .parse()
+            .with_context(|| format!("Bad threshold in '{test}'"))?;
+        let boundary = match rest {
+            [] | ["empty"] => Boundary::Empty,
+            ["wrap"] => Boundary::Wrap,
+            ["reflect"] => Boundary::Reflect,
+            _ => bail!("Unknown boundary '{}'", rest.join(",")),
+        };
+        Ok(Rule::new(stencil, comparison, threshold).with_boundary(boundary))
+    }
+}
+
+/// A map of bales that rules can be applied to, addressed by (row, column)
+/// positions in the input.
+trait BaleMap {
+    /// The number of rows and columns in the input.
+    fn dims(&self) -> (usize, usize);
+    fn is_bale(&self, pos: (usize, usize)) -> bool;
+    fn remove(&mut self, pos: (usize, usize));
+    /// The positions of every bale, in no particular order.
+    fn bales(&self) -> impl Iterator<Item = (usize, usize)> + '_;
+}
+
+/// A bordered map, as `MapFormat::read` builds it.
+impl BaleMap for Array2<u8> {
+    fn dims(&self) -> (usize, usize) {
+        let (rows, cols) = self.dim();
+        (rows - 2, cols - 2)
+    }
+
+    fn is_bale(&self, (row, col): (usize, usize)) -> bool {
+        self[[row + 1, col + 1]] == 1
+    }
+
+    fn remove(&mut self, (row, col): (usize, usize)) {
+        self[[row + 1, col + 1]] = 0;
+    }
+
+    fn bales(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        inner(self)
+            .indexed_iter()
+            .filter(|&(_, &cell)| cell == 1)
+            .map(|(pos, _)| pos)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+/// A map that only stores where the bales are, for inputs too big or too
+/// empty to be worth a dense array.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SparseMap {
+    dims: (usize, usize),
+    bales: HashSet<(usize, usize)>,
+}
+
+impl MapFormat {
+    /// Reads a map as a `SparseMap`, holding no more than one line of the
+    /// text at a time, along with the rows lenient reading padded out.
+    fn read_sparse<R: BufRead>(&self, rdr: R) -> Result<(SparseMap, Vec<PaddedRow>), MapError> {
+        let mut lines = self.rows(rdr);
+        let mut bales = HashSet::new();
+        let mut num_rows = 0;
+        for row in &mut lines {
+            let (_, row) = row?;
+            if row.is_empty() {
+                continue;
             }
+            let cols = row.iter().enumerate().filter(|&(_, &cell)| cell == 1);
+            bales.extend(cols.map(|(col, _)| (num_rows, col)));
+            num_rows += 1;
         }
+        let width = lines.width().ok_or(MapError::Empty)?;
+        let map = SparseMap {
+            dims: (num_rows, width),
+            bales,
+        };
+        Ok((map, lines.padded()))
+    }
+}
+
+/// Maps at least this many columns wide stay sparse, however full they are.
+const SPARSE_MIN_WIDTH: usize = 10_000;
+/// Maps with at most this fraction of their cells holding bales stay sparse.
+const SPARSE_MAX_DENSITY: f64 = 0.05;
+
+impl SparseMap {
+    /// The fraction of cells holding a bale.
+    fn density(&self) -> f64 {
+        self.bales.len() as f64 / (self.dims.0 * self.dims.1).max(1) as f64
+    }
+
+    /// Whether the map is wide or empty enough to be worth keeping sparse,
+    /// rather than switching to a dense array.
+    fn stays_sparse(&self) -> bool {
+        self.dims.1 >= SPARSE_MIN_WIDTH || self.density() <= SPARSE_MAX_DENSITY
+    }
+
+    /// The same map as a dense array with a border, as `MapFormat::read` builds it.
+    fn to_dense(&self) -> Array2<u8> {
+        let mut map = Array::zeros((self.dims.0 + 2, self.dims.1 + 2));
+        for &(row, col) in &self.bales {
+            map[[row + 1, col + 1]] = 1;
+        }
+        map
+    }
+}
+
+/// The number of bales accessible under `rule`, and then the bales removed
+/// in each synchronous round.
+fn apply_rule<M: BaleMap>(map: &mut M, rule: &Rule) -> Result<(usize, Rounds)> {
+    let num = num_accessable_bales(map, rule)?;
+    Ok((num, removal_rounds(map, RemovalMode::Synchronous, rule)?))
+}
+
+impl BaleMap for SparseMap {
+    fn dims(&self) -> (usize, usize) {
+        self.dims
+    }
+
+    fn is_bale(&self, pos: (usize, usize)) -> bool {
+        self.bales.contains(&pos)
+    }
+
+    fn remove(&mut self, pos: (usize, usize)) {
+        self.bales.remove(&pos);
+    }
+
+    fn bales(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.bales.iter().copied()
+    }
+}
+
+/// A rule laid over a map of type `M`, ready to count neighbours. This is all
+/// the removal engine needs, whatever the map and however its cells are found.
+trait Neighbors<M> {
+    /// Where a cell is. Positions are ordered as the map is scanned.
+    type Pos: Clone + Ord + Hash;
+
+    fn rule(&self) -> &Rule;
+    fn is_bale(&self, map: &M, pos: &Self::Pos) -> bool;
+    fn remove(&self, map: &mut M, pos: &Self::Pos);
+    /// The positions of every bale, in no particular order.
+    fn bales(&self, map: &M) -> Vec<Self::Pos>;
+    /// The number of bales in the neighbourhood of `pos`.
+    fn count(&self, map: &M, pos: &Self::Pos) -> usize;
+    /// Every cell whose neighbourhood includes `pos`, as many times as it's
+    /// counted there.
+    fn reaching(&self, pos: &Self::Pos) -> impl Iterator<Item = Self::Pos> + '_;
+
+    fn is_accessible(&self, map: &M, pos: &Self::Pos) -> bool {
+        self.is_bale(map, pos) && self.rule().accepts(self.count(map, pos))
     }
-    count
 }
 
-fn remove_all_possible(map: &mut Array2<u8>) -> usize {
-    let mut count = 0;
+/// A rule laid over a map of a given size.
+struct Neighborhood {
+    rule: Rule,
+    dims: (usize, usize),
+    /// Stencil offsets for even and odd rows.
+    offsets: [Vec<(isize, isize)>; 2],
+    /// For each row, and then each column, the indices past the edges as well
+    /// as on the map that land on it.
+    sources: [Vec<Vec<isize>>; 2],
+}
+
+impl Neighborhood {
+    fn new(rule: Rule, dims: (usize, usize)) -> Result<Self> {
+        let offsets = [rule.stencil.offsets(false), rule.stencil.offsets(true)];
+        let reach = |axis: fn(&(isize, isize)) -> isize| {
+            offsets
+                .iter()
+                .flatten()
+                .map(|off| axis(off).abs())
+                .max()
+                .unwrap_or(0)
+        };
+        let (row_reach, col_reach) = (reach(|off| off.0), reach(|off| off.1));
+        rule.boundary.check_fits(row_reach as usize, dims.0)?;
+        rule.boundary.check_fits(col_reach as usize, dims.1)?;
+        // Wrapping an odd number of rows puts two even rows next to each other,
+        // where hex cells would see neighbours that don't see them back.
+        ensure!(
+            !(rule.stencil == Stencil::Hex && rule.boundary == Boundary::Wrap && dims.0 % 2 == 1),
+            "A hex stencil can only wrap an even number of rows, not {}",
+            dims.0
+        );
+        Ok(Neighborhood {
+            rule,
+            dims,
+            sources: [
+                rule.boundary.sources(row_reach as usize, dims.0),
+                rule.boundary.sources(col_reach as usize, dims.1),
+            ],
+            offsets,
+        })
+    }
+}
+
+impl<M: BaleMap> Neighbors<M> for Neighborhood {
+    type Pos = (usize, usize);
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn is_bale(&self, map: &M, &pos: &(usize, usize)) -> bool {
+        map.is_bale(pos)
+    }
+
+    fn remove(&self, map: &mut M, &pos: &(usize, usize)) {
+        map.remove(pos);
+    }
+
+    fn bales(&self, map: &M) -> Vec<(usize, usize)> {
+        map.bales().collect()
+    }
+
+    fn count(&self, map: &M, &(row, col): &(usize, usize)) -> usize {
+        let boundary = self.rule.boundary;
+        self.offsets[row % 2]
+            .iter()
+            .filter_map(|&(dr, dc)| {
+                let nrow = boundary.resolve(row as isize + dr, self.dims.0)?;
+                let ncol = boundary.resolve(col as isize + dc, self.dims.1)?;
+                map.is_bale((nrow, ncol)).then_some(())
+            })
+            .count()
+    }
+
+    fn reaching(&self, &(row, col): &(usize, usize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (rows, cols) = (self.dims.0 as isize, self.dims.1 as isize);
+        let sources = self.sources[0][row]
+            .iter()
+            .flat_map(move |&r| self.sources[1][col].iter().map(move |&c| (r, c)));
+        sources.flat_map(move |(r, c)| {
+            // Each cell uses the offsets for its own row.
+            self.offsets
+                .iter()
+                .enumerate()
+                .flat_map(move |(odd, offsets)| {
+                    offsets.iter().filter_map(move |&(dr, dc)| {
+                        let (nrow, ncol) = (r - dr, c - dc);
+                        let on_map = (0..rows).contains(&nrow) && (0..cols).contains(&ncol);
+                        (on_map && nrow as usize % 2 == odd)
+                            .then_some((nrow as usize, ncol as usize))
+                    })
+                })
+        })
+    }
+}
+
+/// The cells of a bordered map that hold the input.
+fn inner(map: &Array2<u8>) -> ArrayView2<'_, u8> {
+    let (rows, cols) = map.dim();
+    map.slice(s![1..rows - 1, 1..cols - 1])
+}
+
+fn num_accessable_bales<M: BaleMap>(map: &M, rule: &Rule) -> Result<usize> {
+    let nbhd = Neighborhood::new(*rule, map.dims())?;
+    Ok(map
+        .bales()
+        .filter(|pos| nbhd.is_accessible(map, pos))
+        .count())
+}
+
+/// The bales removed in each round, as (row, column) positions in the input.
+type Rounds = Vec<Vec<(usize, usize)>>;
+
+/// How the bales removed in a round affect the rest of that round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RemovalMode {
+    /// Every removal in a round is decided against the map as it stood when
+    /// the round began.
+    Synchronous,
+    /// The map is scanned row by row and bales are removed as they are found,
+    /// so a removal frees any later bale in the same round.
+    InPlace,
+}
+
+/// Removes bales in rounds until none are accessible under `rule`, returning
+/// the bales removed in each round as (row, column) positions in the input.
+fn removal_rounds<M: BaleMap>(map: &mut M, mode: RemovalMode, rule: &Rule) -> Result<Rounds> {
+    let nbhd = Neighborhood::new(*rule, map.dims())?;
+    Ok(remove_in_rounds(map, &nbhd, mode))
+}
+
+/// Removes bales in rounds until none are accessible under the rule `nbhd`
+/// lays over `map`, returning the bales removed in each round.
+///
+/// Every bale keeps a count of the bales in its neighbourhood, taken down as
+/// they go, and is only looked at again once its count comes to satisfy the
+/// rule. So each bale is counted once and each removal touches only its own
+/// neighbourhood, however many rounds there are.
+fn remove_in_rounds<M, N: Neighbors<M>>(
+    map: &mut M,
+    nbhd: &N,
+    mode: RemovalMode,
+) -> Vec<Vec<N::Pos>> {
+    let rule = nbhd.rule();
+    let mut next = nbhd.bales(map);
+    // Only the bales still standing have a count.
+    let mut counts: HashMap<N::Pos, usize> = next
+        .iter()
+        .map(|pos| (pos.clone(), nbhd.count(map, pos)))
+        .collect();
+    let accessible = |counts: &HashMap<N::Pos, usize>, pos: &N::Pos| {
+        counts.get(pos).is_some_and(|&count| rule.accepts(count))
+    };
+    let mut rounds = Vec::new();
     loop {
-        let removed = remove_and_count(map);
-        count += removed;
-        if removed == 0 {
-            break;
+        next.sort_unstable();
+        next.dedup();
+        if mode == RemovalMode::Synchronous {
+            next.retain(|pos| accessible(&counts, pos));
+        }
+        let mut scan = std::mem::take(&mut next).into_iter().peekable();
+        // Bales ahead of the scan that removals in place have freed this round.
+        let mut freed = BinaryHeap::new();
+        let mut round = Vec::new();
+        loop {
+            let from_freed = match (scan.peek(), freed.peek()) {
+                (None, None) => break,
+                (Some(ahead), Some(Reverse(near))) => near < ahead,
+                (ahead, _) => ahead.is_none(),
+            };
+            let pos = if from_freed {
+                freed.pop().unwrap().0
+            } else {
+                scan.next().unwrap()
+            };
+            if mode == RemovalMode::InPlace && !accessible(&counts, &pos) {
+                continue;
+            }
+            nbhd.remove(map, &pos);
+            counts.remove(&pos);
+            for near in nbhd.reaching(&pos) {
+                let Some(count) = counts.get_mut(&near) else {
+                    continue;
+                };
+                *count -= 1;
+                // Queued when the count first satisfies the rule, not on every removal.
+                if rule.accepts(*count) && !rule.accepts(*count + 1) {
+                    // A bale ahead of the scan is looked at this round when in place.
+                    if mode == RemovalMode::InPlace && near > pos {
+                        freed.push(Reverse(near));
+                    } else {
+                        next.push(near);
+                    }
+                }
+            }
+            round.push(pos);
+        }
+        if round.is_empty() {
+            return rounds;
+        }
+        rounds.push(round);
+    }
+}
+
+impl MapFormat {
+    /// Reads a grid of any number of dimensions as stacked 2D slices, along
+    /// with the rows lenient reading padded out.
+    ///
+    /// A blank line separates the slices of a 3D grid, two blank lines separate
+    /// the 3D blocks of a 4D grid, and so on. Every slice must be the same shape.
+    fn read_grid<R: BufRead>(&self, rdr: R) -> Result<(ArrayD<u8>, Vec<PaddedRow>), MapError> {
+        let mut lines = self.rows(rdr);
+        let mut rows: Vec<Vec<u8>> = Vec::new();
+        // Where the current row sits along every axis but the last.
+        let mut index: Vec<usize> = vec![0];
+        let mut extent = vec![1];
+        let mut blank_lines = 0;
+        for row in &mut lines {
+            let (_, row) = row?;
+            if row.is_empty() {
+                blank_lines += 1;
+                continue;
+            }
+            if !rows.is_empty() {
+                if blank_lines >= index.len() {
+                    // Start a new outermost axis around everything so far.
+                    index.splice(0..0, std::iter::repeat_n(0, blank_lines + 1 - index.len()));
+                    extent.splice(0..0, std::iter::repeat_n(1, blank_lines + 1 - extent.len()));
+                }
+                // Move on along the axis the blank lines pick, back to the start of those inside it.
+                let axis = index.len() - 1 - blank_lines;
+                index[axis] += 1;
+                index[axis + 1..].fill(0);
+                extent[axis] = extent[axis].max(index[axis] + 1);
+            }
+            blank_lines = 0;
+            rows.push(row);
+        }
+        let width = lines.width().ok_or(MapError::Empty)?;
+        extent.push(width);
+        // Rows are numbered in order without repeats, so if there are as many as
+        // the extent has room for, every slice is complete.
+        let shape = IxDyn(&extent);
+        if rows.len() * width != shape.size() {
+            return Err(MapError::UnevenSlices { shape: extent });
+        }
+        let mut cells = Vec::with_capacity(shape.size());
+        for row in rows {
+            let padding = width - row.len();
+            cells.extend(row);
+            cells.extend(std::iter::repeat_n(0, padding));
+        }
+        let grid = ArrayD::from_shape_vec(shape, cells).expect("The rows fill the grid");
+        Ok((grid, lines.padded()))
+    }
+}
+
+impl Stencil {
+    /// Offsets to the neighbours of a cell in a grid with `ndim` axes.
+    fn offsets_nd(self, ndim: usize) -> Result<Vec<Vec<isize>>> {
+        let radius = match self {
+            Stencil::VonNeumann => {
+                return Ok((0..ndim)
+                    .flat_map(|axis| {
+                        [-1, 1].map(|step| {
+                            let mut off = vec![0; ndim];
+                            off[axis] = step;
+                            off
+                        })
+                    })
+                    .collect());
+            }
+            Stencil::Moore => 1,
+            Stencil::Radius(r) => r as isize,
+            Stencil::Hex => bail!("The hex stencil only applies to 2D maps"),
+        };
+        let cube = (0..ndim).fold(vec![vec![]], |offsets, _| {
+            offsets
+                .iter()
+                .flat_map(|off: &Vec<isize>| {
+                    (-radius..=radius).map(move |d| [off.as_slice(), &[d]].concat())
+                })
+                .collect()
+        });
+        Ok(cube
+            .into_iter()
+            .filter(|off| off.iter().any(|&d| d != 0))
+            .collect())
+    }
+}
+
+/// A rule laid over a grid of any number of dimensions.
+struct NeighborhoodNd {
+    rule: Rule,
+    shape: Vec<usize>,
+    offsets: Vec<Vec<isize>>,
+    /// For each axis and each position along it, the indices past the edges
+    /// as well as on the grid that land on it.
+    sources: Vec<Vec<Vec<isize>>>,
+}
+
+impl NeighborhoodNd {
+    fn new(rule: Rule, shape: &[usize]) -> Result<Self> {
+        let offsets = rule.stencil.offsets_nd(shape.len())?;
+        let sources = shape
+            .iter()
+            .enumerate()
+            .map(|(axis, &len)| {
+                let reach = offsets.iter().map(|off| off[axis].unsigned_abs()).max();
+                let reach = reach.unwrap_or(0);
+                rule.boundary.check_fits(reach, len)?;
+                Ok(rule.boundary.sources(reach, len))
+            })
+            .collect::<Result<_>>()?;
+        Ok(NeighborhoodNd {
+            rule,
+            shape: shape.to_vec(),
+            offsets,
+            sources,
+        })
+    }
+}
+
+impl Neighbors<ArrayD<u8>> for NeighborhoodNd {
+    type Pos = Vec<usize>;
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn is_bale(&self, grid: &ArrayD<u8>, pos: &Vec<usize>) -> bool {
+        grid[pos.as_slice()] == 1
+    }
+
+    fn remove(&self, grid: &mut ArrayD<u8>, pos: &Vec<usize>) {
+        grid[pos.as_slice()] = 0;
+    }
+
+    fn bales(&self, grid: &ArrayD<u8>) -> Vec<Vec<usize>> {
+        grid.indexed_iter()
+            .filter(|&(_, &cell)| cell == 1)
+            .map(|(pos, _)| pos.slice().to_vec())
+            .collect()
+    }
+
+    fn count(&self, grid: &ArrayD<u8>, pos: &Vec<usize>) -> usize {
+        let boundary = self.rule.boundary;
+        self.offsets
+            .iter()
+            .filter(|off| {
+                let near: Option<Vec<usize>> = (pos.iter().zip(off.iter()).zip(&self.shape))
+                    .map(|((&p, &d), &len)| boundary.resolve(p as isize + d, len))
+                    .collect();
+                near.is_some_and(|near| grid[near.as_slice()] == 1)
+            })
+            .count()
+    }
+
+    fn reaching(&self, pos: &Vec<usize>) -> impl Iterator<Item = Vec<usize>> + '_ {
+        let sources: Vec<&[isize]> = (pos.iter().zip(&self.sources))
+            .map(|(&p, sources)| sources[p].as_slice())
+            .collect();
+        let choices: Vec<usize> = sources.iter().map(|s| s.len()).collect();
+        // Every way of picking a source along each axis, without listing them up front.
+        ndarray::indices(choices).into_iter().flat_map(move |pick| {
+            let from: Vec<isize> = (sources.iter().zip(pick.slice()))
+                .map(|(sources, &i)| sources[i])
+                .collect();
+            self.offsets.iter().filter_map(move |off| {
+                (from.iter().zip(off).zip(&self.shape))
+                    .map(|((&p, &d), &len)| {
+                        let q = p - d;
+                        (0..len as isize).contains(&q).then_some(q as usize)
+                    })
+                    .collect()
+            })
+        })
+    }
+}
+
+fn num_accessable_cells(grid: &ArrayD<u8>, rule: &Rule) -> Result<usize> {
+    let nbhd = NeighborhoodNd::new(*rule, grid.shape())?;
+    Ok(nbhd
+        .bales(grid)
+        .iter()
+        .filter(|pos| nbhd.is_accessible(grid, pos))
+        .count())
+}
+
+/// `removal_rounds` for a grid of any number of dimensions, returning the
+/// position of each bale along every axis.
+fn removal_rounds_nd(
+    grid: &mut ArrayD<u8>,
+    mode: RemovalMode,
+    rule: &Rule,
+) -> Result<Vec<Vec<Vec<usize>>>> {
+    let nbhd = NeighborhoodNd::new(*rule, grid.shape())?;
+    Ok(remove_in_rounds(grid, &nbhd, mode))
+}
+
+/// What a cell of the input shows after some rounds of removals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shown {
+    Empty,
+    Bale,
+    /// A bale taken away in the given round, counting from zero.
+    Removed(usize),
+}
+
+/// The input cells before any removals, and then after each round.
+fn removal_frames(map: &Array2<u8>, rounds: &[Vec<(usize, usize)>]) -> Vec<Array2<Shown>> {
+    let mut frame = inner(map).map(|&cell| if cell == 1 { Shown::Bale } else { Shown::Empty });
+    let mut frames = vec![frame.clone()];
+    for (round, removed) in rounds.iter().enumerate() {
+        for &pos in removed {
+            frame[pos] = Shown::Removed(round);
         }
+        frames.push(frame.clone());
     }
-    count
+    frames
+}
+
+/// Draws a frame as text, marking each removed bale with its round in base 36.
+fn ascii_frame(frame: &Array2<Shown>) -> String {
+    let mut text = String::with_capacity(frame.len() + frame.nrows());
+    for row in frame.rows() {
+        text.extend(row.iter().map(|shown| match shown {
+            Shown::Empty => '.',
+            Shown::Bale => '@',
+            Shown::Removed(round) => char::from_digit((round % 36) as u32, 36).unwrap(),
+        }));
+        text.push('\n');
+    }
+    text
+}
+
+/// Colours for empty cells, standing bales, and then each round of removals
+/// running from red through to violet. Rounds share colours past 254 of them.
+fn wave_palette(num_rounds: usize) -> Vec<Rgb> {
+    let num_colours = num_rounds.clamp(1, 254);
+    let mut palette = vec![[24, 24, 32], [220, 190, 90]];
+    palette.extend((0..num_colours).map(|i| {
+        let hue = 270.0 * i as f64 / (num_colours.max(2) - 1) as f64;
+        // Fully saturated colours around the wheel, one sixth at a time.
+        let x = 1.0 - ((hue / 60.0) % 2.0 - 1.0).abs();
+        let (r, g, b) = match (hue / 60.0) as u32 {
+            0 => (1.0, x, 0.0),
+            1 => (x, 1.0, 0.0),
+            2 => (0.0, 1.0, x),
+            3 => (0.0, x, 1.0),
+            _ => (x, 0.0, 1.0),
+        };
+        [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+    }));
+    palette
+}
+
+/// Draws a frame as an image in the colours of `wave_palette(num_rounds)`.
+fn image_frame(frame: &Array2<Shown>, num_rounds: usize, scale: usize) -> Frame {
+    let num_colours = num_rounds.clamp(1, 254);
+    let cells: Vec<Vec<u8>> = frame
+        .rows()
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .map(|shown| match *shown {
+                    Shown::Empty => 0,
+                    Shown::Bale => 1,
+                    Shown::Removed(round) => (2 + round * num_colours / num_rounds) as u8,
+                })
+                .collect()
+        })
+        .collect();
+    Frame::from_cells(&cells, scale)
 }
 
-fn main() {
+fn main() -> Result<()> {
+    init_logging();
     let in_path = get_input("input_d4.txt");
     let mut in_file = File::open(&in_path).unwrap();
-    let mut map = read_map(&mut in_file);
+    // Read the map leniently with `--lenient`, and with extra glyphs given as `--legend '#=@,o=.'`.
+    let mut format = MapFormat::strict();
+    if has_flag("--lenient") {
+        format = format.lenient();
+    }
+    if let Some(spec) = flag_value("--legend") {
+        format = format.with_legend(&spec)?;
+    }
+    let (input_map, padded) = format.read(&mut in_file)?;
+    for row in padded {
+        log::warn!("{row}");
+    }
     {
-        let num = num_accessable_bales(&map);
+        let num = num_accessable_bales(&input_map, &Rule::default())?;
         println!("Day 4 pt 1: There are {} accessable bales.", num);
     }
 
     {
-        let removed = remove_all_possible(&mut map);
+        let rule = Rule::default();
+        let rounds = removal_rounds(&mut input_map.clone(), RemovalMode::Synchronous, &rule)?;
+        let removed: usize = rounds.iter().map(Vec::len).sum();
         println!("Day 4 pt 2: {} can be removed.", removed);
+        // Compare how many rounds each removal mode takes with `--rounds`.
+        if has_flag("--rounds") {
+            let in_place = removal_rounds(&mut input_map.clone(), RemovalMode::InPlace, &rule)?;
+            println!(
+                "Day 4 pt 2: That takes {} synchronous rounds, or {} in place.",
+                rounds.len(),
+                in_place.len()
+            );
+        }
+    }
+
+    // Try a variant forklift rule, given as e.g. `--rule hex,<3,wrap`.
+    let mut rule = Rule::default();
+    if let Some(spec) = flag_value("--rule") {
+        rule = spec.parse()?;
+        let num = num_accessable_bales(&input_map, &rule)?;
+        let rounds = removal_rounds(&mut input_map.clone(), RemovalMode::Synchronous, &rule)?;
+        let removed: usize = rounds.iter().map(Vec::len).sum();
+        println!(
+            "Day 4 with rule {spec}: {num} accessable bales, {removed} removed in {} rounds.",
+            rounds.len()
+        );
     }
+
+    // Apply the rule to another map with `--map FILE`, left sparse if it's too
+    // wide or too empty to be worth holding densely.
+    if let Some(path) = flag_value("--map") {
+        let in_file = File::open(&path).with_context(|| format!("Opening {path}"))?;
+        let (mut map, padded) = format
+            .read_sparse(BufReader::new(in_file))
+            .with_context(|| format!("In {path}"))?;
+        for row in padded {
+            log::warn!("{path}: {row}");
+        }
+        let (dims, density) = (map.dims, map.density());
+        let (backend, (num, rounds)) = if map.stays_sparse() {
+            ("sparse", apply_rule(&mut map, &rule)?)
+        } else {
+            ("dense", apply_rule(&mut map.to_dense(), &rule)?)
+        };
+        let removed: usize = rounds.iter().map(Vec::len).sum();
+        println!(
+            "Day 4 {backend} {}x{} map, {:.1}% full: {num} accessable bales, {removed} removed in {} rounds.",
+            dims.0,
+            dims.1,
+            100.0 * density,
+            rounds.len()
+        );
+    }
+
+    // Apply the rule to a grid of stacked slices, in 3D or more, with `--grid FILE`.
+    if let Some(path) = flag_value("--grid") {
+        let in_file = File::open(&path).with_context(|| format!("Opening {path}"))?;
+        let (mut grid, padded) = format
+            .read_grid(BufReader::new(in_file))
+            .with_context(|| format!("In {path}"))?;
+        for row in padded {
+            log::warn!("{path}: {row}");
+        }
+        let num = num_accessable_cells(&grid, &rule)?;
+        let rounds = removal_rounds_nd(&mut grid, RemovalMode::Synchronous, &rule)?;
+        let removed: usize = rounds.iter().map(Vec::len).sum();
+        println!(
+            "Day 4 {:?} grid: {num} accessable bales, {removed} removed in {} rounds.",
+            grid.shape(),
+            rounds.len()
+        );
+    }
+
+    // Show the removal rounds with `--ascii`, `--ppm DIR` or `--gif FILE`.
+    let ascii = has_flag("--ascii");
+    let ppm_dir = flag_value("--ppm");
+    let gif_path = flag_value("--gif");
+    if ascii || ppm_dir.is_some() || gif_path.is_some() {
+        let rounds = removal_rounds(&mut input_map.clone(), RemovalMode::Synchronous, &rule)?;
+        let frames = removal_frames(&input_map, &rounds);
+        if ascii {
+            for (i, frame) in frames.iter().enumerate() {
+                println!("After {i} rounds:\n{}", ascii_frame(frame));
+            }
+        }
+        let palette = wave_palette(rounds.len());
+        let images: Vec<Frame> = frames
+            .iter()
+            .map(|frame| image_frame(frame, rounds.len(), 4))
+            .collect();
+        if let Some(dir) = ppm_dir {
+            std::fs::create_dir_all(&dir)?;
+            for (i, image) in images.iter().enumerate() {
+                let path = Path::new(&dir).join(format!("round_{i:03}.ppm"));
+                let mut out = BufWriter::new(File::create(&path)?);
+                write_ppm(&mut out, image, &palette)
+                    .with_context(|| format!("Writing {}", path.display()))?;
+            }
+            println!("Wrote {} frames to {dir}", images.len());
+        }
+        if let Some(path) = gif_path {
+            let mut out = BufWriter::new(File::create(&path)?);
+            write_gif(&mut out, &images, &palette, 20)
+                .with_context(|| format!("Writing {path}"))?;
+            println!("Wrote {} frames to {path}", images.len());
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aoc2025::Lcg;
+
+    /// Reads a map in the puzzle's format.
+    fn read_map<R: Read>(rdr: &mut R) -> Result<Array2<u8>, MapError> {
+        MapFormat::strict().read(rdr).map(|(map, _)| map)
+    }
+
+    fn read_sparse(text: &[u8]) -> Result<SparseMap, MapError> {
+        MapFormat::strict().read_sparse(text).map(|(map, _)| map)
+    }
+
+    fn read_grid(text: &[u8]) -> Result<ArrayD<u8>, MapError> {
+        MapFormat::strict().read_grid(text).map(|(grid, _)| grid)
+    }
 
     static INPUT: &str = r#"
 ..@@.@@@@.
@@ -127,17 +1230,567 @@ mod tests {
     #[test]
     fn test_read_example_input() {
         let mut input_file = std::io::Cursor::new(INPUT.trim().to_owned());
-        let map = read_map(&mut input_file);
+        let map = read_map(&mut input_file).unwrap();
         assert_eq!(map.shape(), vec![13, 12]);
-        assert_eq!(count_neighbors(&map, 1, 3), 3);
-        assert_eq!(num_accessable_bales(&map), 13);
+        let nbhd = Neighborhood::new(Rule::default(), map.dims()).unwrap();
+        assert_eq!(nbhd.count(&map, &(0, 2)), 3);
+        assert_eq!(num_accessable_bales(&map, &Rule::default()).unwrap(), 13);
     }
 
     #[test]
     fn test_pt_2() {
         let mut input_file = std::io::Cursor::new(INPUT.trim().to_owned());
-        let mut map = read_map(&mut input_file);
-        let possible = remove_all_possible(&mut map);
-        assert_eq!(possible, 43);
+        let mut map = read_map(&mut input_file).unwrap();
+        let rounds = removal_rounds(&mut map, RemovalMode::Synchronous, &Rule::default()).unwrap();
+        assert_eq!(rounds.iter().map(Vec::len).sum::<usize>(), 43);
+    }
+
+    /// Removes bales by sweeping the whole map until nothing changes,
+    /// returning the bales removed by each sweep.
+    fn sweep_rounds(
+        map: &mut Array2<u8>,
+        mode: RemovalMode,
+        rule: &Rule,
+    ) -> Vec<Vec<(usize, usize)>> {
+        let (rows, cols) = map.dim();
+        let nbhd = Neighborhood::new(*rule, (rows - 2, cols - 2)).unwrap();
+        let mut rounds = Vec::new();
+        loop {
+            let snapshot = map.clone();
+            let mut removed = Vec::new();
+            for irow in 0..rows - 2 {
+                for jcol in 0..cols - 2 {
+                    let seen = match mode {
+                        RemovalMode::Synchronous => &snapshot,
+                        RemovalMode::InPlace => &*map,
+                    };
+                    if nbhd.is_accessible(seen, &(irow, jcol)) {
+                        removed.push((irow, jcol));
+                        map[[irow + 1, jcol + 1]] = 0;
+                    }
+                }
+            }
+            if removed.is_empty() {
+                return rounds;
+            }
+            rounds.push(removed);
+        }
+    }
+
+    #[test]
+    fn test_input_matches_sweep() {
+        let in_path = get_input("input_d4.txt");
+        let mut in_file = File::open(&in_path).unwrap();
+        let map = read_map(&mut in_file).unwrap();
+        for mode in [RemovalMode::Synchronous, RemovalMode::InPlace] {
+            let mut removed = map.clone();
+            let mut swept = map.clone();
+            assert_eq!(
+                removal_rounds(&mut removed, mode, &Rule::default()).unwrap(),
+                sweep_rounds(&mut swept, mode, &Rule::default())
+            );
+            assert_eq!(removed, swept);
+        }
+    }
+
+    #[test]
+    fn test_removal_rounds() {
+        let mut input_file = std::io::Cursor::new(INPUT.trim().to_owned());
+        let map = read_map(&mut input_file).unwrap();
+        let rounds =
+            removal_rounds(&mut map.clone(), RemovalMode::Synchronous, &Rule::default()).unwrap();
+        let sizes: Vec<usize> = rounds.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![13, 12, 7, 5, 2, 1, 1, 1, 1]);
+        assert_eq!(rounds[0][..3], [(0, 2), (0, 3), (0, 5)]);
+
+        let rounds =
+            removal_rounds(&mut map.clone(), RemovalMode::InPlace, &Rule::default()).unwrap();
+        assert_eq!(rounds.iter().map(Vec::len).sum::<usize>(), 43);
+        assert!(rounds.len() < 9);
+        assert!(rounds[0].len() > 13);
+    }
+
+    #[test]
+    fn test_removal_rounds_match_sweeps() {
+        let mut rng = Lcg::new(37);
+        let mut map: Array2<u8> = Array::zeros((42, 62));
+        for irow in 1..41 {
+            for jcol in 1..61 {
+                map[[irow, jcol]] = (rng.below(10) < 7) as u8;
+            }
+        }
+        let rules = [
+            Rule::default(),
+            "von-neumann,<2".parse().unwrap(),
+            "radius2,<=11,reflect".parse().unwrap(),
+            "hex,<4,wrap".parse().unwrap(),
+            // Removals can make a bale inaccessible under this one.
+            "moore,>5,wrap".parse().unwrap(),
+        ];
+        for rule in &rules {
+            for mode in [RemovalMode::Synchronous, RemovalMode::InPlace] {
+                let mut removed = map.clone();
+                let mut swept = map.clone();
+                assert_eq!(
+                    removal_rounds(&mut removed, mode, rule).unwrap(),
+                    sweep_rounds(&mut swept, mode, rule),
+                    "{rule:?} {mode:?}"
+                );
+                assert_eq!(removed, swept);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rule_counts() {
+        let mut input_file = std::io::Cursor::new("@@.\n.@@\n@..\n");
+        let map = read_map(&mut input_file).unwrap();
+        let count = |spec: &str, pos| {
+            let rule: Rule = spec.parse().unwrap();
+            Neighborhood::new(rule, (3, 3)).unwrap().count(&map, &pos)
+        };
+        assert_eq!(count("moore,<4", (1, 1)), 4);
+        assert_eq!(count("von-neumann,<4", (1, 1)), 2);
+        assert_eq!(count("radius2,<4", (0, 0)), 4);
+        // Odd rows lean right, so (1, 1) touches (0, 1) and (0, 2) above it.
+        assert_eq!(count("hex,<4", (1, 1)), 2);
+        assert_eq!(count("hex,<4", (0, 1)), 2);
+        assert_eq!(count("moore,<4", (0, 0)), 2);
+        assert_eq!(count("moore,<4,wrap", (0, 0)), 4);
+        assert_eq!(count("moore,<4,reflect", (0, 0)), 6);
+        assert_eq!(count("von-neumann,<4,reflect", (0, 0)), 2);
+
+        assert_eq!(
+            num_accessable_bales(&map, &"moore,>=2".parse().unwrap()).unwrap(),
+            4
+        );
+        assert_eq!(
+            num_accessable_bales(&map, &"hex,=0".parse().unwrap()).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_rules_must_fit_the_map() {
+        // Wrapping or reflecting one row would count its cells, and the bale
+        // itself, several times over.
+        let row = read_map(&mut "@@@".as_bytes()).unwrap();
+        for spec in ["moore,<4,wrap", "moore,<4,reflect", "radius2,<4,wrap"] {
+            let rule: Rule = spec.parse().unwrap();
+            let err = num_accessable_bales(&row, &rule).unwrap_err();
+            assert!(err.to_string().contains("not 1"), "{spec}: {err}");
+            assert!(removal_rounds(&mut row.clone(), RemovalMode::InPlace, &rule).is_err());
+            let grid = inner(&row).into_dyn().to_owned();
+            assert!(num_accessable_cells(&grid, &rule).is_err(), "{spec}");
+        }
+        assert_eq!(
+            num_accessable_bales(&row, &"von-neumann,<2,empty".parse().unwrap()).unwrap(),
+            2
+        );
+
+        // Three cells are just enough to wrap a Moore neighbourhood.
+        let square = read_map(&mut "@@@\n@@@\n@@@".as_bytes()).unwrap();
+        let nbhd = Neighborhood::new("moore,<4,wrap".parse().unwrap(), (3, 3)).unwrap();
+        assert_eq!(nbhd.count(&square, &(0, 0)), 8);
+        assert!(Neighborhood::new("radius2,<4,reflect".parse().unwrap(), (3, 3)).is_ok());
+        assert!(Neighborhood::new("radius2,<4,reflect".parse().unwrap(), (2, 3)).is_err());
+        assert!(Neighborhood::new("radius2,<4,wrap".parse().unwrap(), (5, 4)).is_err());
+    }
+
+    #[test]
+    fn test_neighborhoods_are_symmetric() {
+        assert!(Neighborhood::new("hex,<4,wrap".parse().unwrap(), (3, 4)).is_err());
+        for (spec, dims) in [
+            ("hex,<4,wrap", (4, 5)),
+            ("hex,<4", (3, 4)),
+            ("moore,<4,wrap", (3, 5)),
+            ("radius2,<4,wrap", (5, 6)),
+        ] {
+            let nbhd = Neighborhood::new(spec.parse().unwrap(), dims).unwrap();
+            let cells = (0..dims.0).flat_map(|row| (0..dims.1).map(move |col| (row, col)));
+            let sees = |a, b| {
+                let only_b = SparseMap {
+                    dims,
+                    bales: HashSet::from([b]),
+                };
+                nbhd.count(&only_b, &a)
+            };
+            for a in cells.clone() {
+                for b in cells.clone() {
+                    assert_eq!(sees(a, b), sees(b, a), "{spec}: {a:?} and {b:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reaching_matches_counts() {
+        // Reflecting counts some cells near the edge twice over.
+        for (spec, dims) in [
+            ("moore,<4,reflect", (3, 4)),
+            ("radius2,<4,reflect", (4, 3)),
+            ("hex,<4,reflect", (5, 4)),
+            ("hex,<4,wrap", (4, 5)),
+            ("von-neumann,<4", (3, 3)),
+        ] {
+            let nbhd = Neighborhood::new(spec.parse().unwrap(), dims).unwrap();
+            let cells = (0..dims.0).flat_map(|row| (0..dims.1).map(move |col| (row, col)));
+            for b in cells.clone() {
+                let only_b = SparseMap {
+                    dims,
+                    bales: HashSet::from([b]),
+                };
+                let counted: Vec<_> = (cells.clone())
+                    .flat_map(|a| std::iter::repeat_n(a, nbhd.count(&only_b, &a)))
+                    .collect();
+                let mut reaching: Vec<_> = Neighbors::<SparseMap>::reaching(&nbhd, &b).collect();
+                reaching.sort_unstable();
+                assert_eq!(reaching, counted, "{spec}: {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        let rule: Rule = " radius3 , <= 10 , reflect".parse().unwrap();
+        assert_eq!(
+            rule,
+            Rule::new(Stencil::Radius(3), Comparison::AtMost, 10).with_boundary(Boundary::Reflect)
+        );
+        assert_eq!("moore,<4".parse::<Rule>().unwrap(), Rule::default());
+        for bad in [
+            "moore",
+            "moore,4",
+            "radius0,<4",
+            "square,<4",
+            "moore,<x",
+            "moore,<4,tile",
+        ] {
+            assert!(bad.parse::<Rule>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_boundary_resolve() {
+        let on = |b: Boundary| (-4..8).map(|i| b.resolve(i, 4)).collect::<Vec<_>>();
+        let some = |v: &[usize]| v.iter().map(|&i| Some(i)).collect::<Vec<_>>();
+        assert_eq!(
+            on(Boundary::Wrap),
+            some(&[0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3])
+        );
+        assert_eq!(
+            on(Boundary::Reflect),
+            some(&[2, 3, 2, 1, 0, 1, 2, 3, 2, 1, 0, 1])
+        );
+        assert_eq!(
+            on(Boundary::Empty)[3..9],
+            [None, Some(0), Some(1), Some(2), Some(3), None]
+        );
+    }
+
+    #[test]
+    fn test_removal_frames() {
+        let mut input_file = std::io::Cursor::new(INPUT.trim().to_owned());
+        let map = read_map(&mut input_file).unwrap();
+        let rounds =
+            removal_rounds(&mut map.clone(), RemovalMode::Synchronous, &Rule::default()).unwrap();
+        let frames = removal_frames(&map, &rounds);
+        assert_eq!(frames.len(), 10);
+        assert_eq!(ascii_frame(&frames[0]).lines().next(), Some("..@@.@@@@."));
+        assert_eq!(ascii_frame(&frames[1]).lines().next(), Some("..00.00@0."));
+        let last = ascii_frame(&frames[9]);
+        assert_eq!(last.matches('@').count(), 71 - 43);
+        assert_eq!(last.matches('8').count(), 1);
+
+        let palette = wave_palette(rounds.len());
+        assert_eq!(palette.len(), 2 + 9);
+        assert_eq!(palette[2], [255, 0, 0]);
+        let image = image_frame(&frames[9], rounds.len(), 2);
+        assert_eq!((image.width, image.height), (20, 22));
+        assert_eq!(image.pixels[..6], [0, 0, 0, 0, 2, 2]);
+        assert!(image.pixels.iter().all(|&px| (px as usize) < palette.len()));
+
+        // Rounds share colours once there are more than the palette can hold.
+        let many = wave_palette(1000);
+        assert_eq!(many.len(), 256);
+        let frame = Array2::from_elem((1, 1), Shown::Removed(999));
+        assert_eq!(image_frame(&frame, 1000, 1).pixels, [255]);
+    }
+
+    #[test]
+    fn test_sparse_matches_dense() {
+        let mut rng = Lcg::new(40);
+        let mut text = String::new();
+        for _ in 0..30 {
+            for _ in 0..50 {
+                text.push(if rng.below(10) < 6 { '@' } else { '.' });
+            }
+            text.push_str("\r\n");
+        }
+        let dense = read_map(&mut text.as_bytes()).unwrap();
+        let sparse = read_sparse(text.as_bytes()).unwrap();
+        assert!(!sparse.stays_sparse());
+        assert_eq!(sparse.to_dense(), dense);
+        assert_eq!(sparse.dims(), dense.dims());
+        let mut bales: Vec<_> = sparse.bales().collect();
+        bales.sort_unstable();
+        assert_eq!(bales, dense.bales().collect::<Vec<_>>());
+
+        for spec in ["moore,<4", "hex,<3,wrap", "radius2,>=12,reflect"] {
+            let rule: Rule = spec.parse().unwrap();
+            assert_eq!(
+                num_accessable_bales(&sparse, &rule).unwrap(),
+                num_accessable_bales(&dense, &rule).unwrap()
+            );
+            for mode in [RemovalMode::Synchronous, RemovalMode::InPlace] {
+                let mut sparse = sparse.clone();
+                let mut dense = dense.clone();
+                assert_eq!(
+                    removal_rounds(&mut sparse, mode, &rule).unwrap(),
+                    removal_rounds(&mut dense, mode, &rule).unwrap(),
+                    "{spec} {mode:?}"
+                );
+                assert_eq!(sparse.bales.len(), dense.bales().count());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparse_wide_map() {
+        // Bales in threes along a row far too wide to be worth storing densely.
+        let mut text = vec![b'.'; 60_000];
+        for col in (0..60_000).step_by(1000) {
+            text[col..col + 3].copy_from_slice(b"@@@");
+        }
+        text.extend_from_slice(b"\n\n");
+        let mut map = read_sparse(text.as_slice()).unwrap();
+        assert_eq!(map.dims(), (1, 60_000));
+        assert_eq!(map.density(), 180.0 / 60_000.0);
+        assert_eq!(
+            num_accessable_bales(&map, &"von-neumann,<2".parse().unwrap()).unwrap(),
+            120
+        );
+        let rounds = removal_rounds(
+            &mut map,
+            RemovalMode::Synchronous,
+            &"von-neumann,<2".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(rounds.iter().map(Vec::len).collect::<Vec<_>>(), [120, 60]);
+
+        assert!(map.stays_sparse());
+        assert!(read_sparse(&b"@@.\n@.\n"[..]).is_err());
+        assert!(read_sparse(&b"\n\n"[..]).is_err());
+
+        // Ragged rows are reported on the line they're on, blank lines included.
+        let ragged = b"@@@\n\n\n@@\n";
+        for err in [
+            read_sparse(ragged).unwrap_err(),
+            read_map(&mut &ragged[..]).unwrap_err(),
+        ] {
+            assert_eq!(err.to_string(), "Line 4: row has 2 cells, but expected 3");
+        }
+    }
+
+    /// Removes bales from a grid by sweeping all of it until nothing changes.
+    fn sweep_rounds_nd(
+        grid: &mut ArrayD<u8>,
+        mode: RemovalMode,
+        rule: &Rule,
+    ) -> Vec<Vec<Vec<usize>>> {
+        let nbhd = NeighborhoodNd::new(*rule, grid.shape()).unwrap();
+        let cells: Vec<Vec<usize>> = grid
+            .indexed_iter()
+            .map(|(pos, _)| pos.slice().to_vec())
+            .collect();
+        let mut rounds = Vec::new();
+        loop {
+            let snapshot = grid.clone();
+            let mut removed = Vec::new();
+            for pos in &cells {
+                let seen = match mode {
+                    RemovalMode::Synchronous => &snapshot,
+                    RemovalMode::InPlace => &*grid,
+                };
+                if nbhd.is_accessible(seen, pos) {
+                    removed.push(pos.clone());
+                    grid[pos.as_slice()] = 0;
+                }
+            }
+            if removed.is_empty() {
+                return rounds;
+            }
+            rounds.push(removed);
+        }
+    }
+
+    fn random_grid(seed: u32, shape: &[usize]) -> ArrayD<u8> {
+        let mut rng = Lcg::new(seed);
+        ArrayD::from_shape_simple_fn(IxDyn(shape), || (rng.below(10) < 6) as u8)
+    }
+
+    #[test]
+    fn test_read_grid() {
+        let grid = read_grid(&b"\n@.@\n.@.\n\n...\n@@@\n\n"[..]).unwrap();
+        assert_eq!(grid.shape(), [2, 2, 3]);
+        assert_eq!(grid[[1, 1, 0]], 1);
+        assert_eq!(grid[[1, 0, 2]], 0);
+
+        let grid = read_grid(&b"@.\n\n.@\n\n\n@@\n\n..\n\n\n..\n\n@.\n"[..]).unwrap();
+        assert_eq!(grid.shape(), [3, 2, 1, 2]);
+        assert_eq!(grid.sum(), 5);
+        assert_eq!(grid[[2, 1, 0, 0]], 1);
+
+        let plain = read_grid(INPUT.as_bytes()).unwrap();
+        let mut input_file = std::io::Cursor::new(INPUT.trim().to_owned());
+        assert_eq!(plain, inner(&read_map(&mut input_file).unwrap()).into_dyn());
+
+        for bad in [
+            "@.\n\n.@\n..\n",
+            "@.\n@\n",
+            "@.\n\n\n.@\n\n..\n",
+            "@x\n",
+            "\n",
+        ] {
+            assert!(read_grid(bad.as_bytes()).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_stencils_nd() {
+        let sizes =
+            |stencil: Stencil| [2, 3, 4].map(|ndim| stencil.offsets_nd(ndim).unwrap().len());
+        assert_eq!(sizes(Stencil::VonNeumann), [4, 6, 8]);
+        assert_eq!(sizes(Stencil::Moore), [8, 26, 80]);
+        assert_eq!(sizes(Stencil::Radius(2)), [24, 124, 624]);
+        assert!(Stencil::Hex.offsets_nd(3).is_err());
+
+        // Only the corners of a full cube have fewer than eight neighbours.
+        let cube = ArrayD::from_elem(IxDyn(&[3, 3, 3]), 1u8);
+        assert_eq!(
+            num_accessable_cells(&cube, &"moore,<8".parse().unwrap()).unwrap(),
+            8
+        );
+        assert_eq!(
+            num_accessable_cells(&cube, &"moore,<8,wrap".parse().unwrap()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_rounds_nd() {
+        // A 2D grid gives the same rounds as the 2D engine.
+        let map = read_map(&mut INPUT.trim().as_bytes()).unwrap();
+        let grid = inner(&map).into_dyn().to_owned();
+        for spec in ["moore,<4", "von-neumann,<3,reflect", "radius2,<=10,wrap"] {
+            let rule: Rule = spec.parse().unwrap();
+            for mode in [RemovalMode::Synchronous, RemovalMode::InPlace] {
+                let flat = removal_rounds(&mut map.clone(), mode, &rule).unwrap();
+                let nd = removal_rounds_nd(&mut grid.clone(), mode, &rule).unwrap();
+                let nd: Vec<Vec<(usize, usize)>> = nd
+                    .iter()
+                    .map(|round| round.iter().map(|pos| (pos[0], pos[1])).collect())
+                    .collect();
+                assert_eq!(flat, nd, "{spec} {mode:?}");
+            }
+        }
+
+        for (seed, shape, spec) in [
+            (41, vec![6, 7, 8], "moore,<9"),
+            (42, vec![5, 6, 7], "von-neumann,<=3,wrap"),
+            (43, vec![4, 4, 5, 5], "moore,<42,reflect"),
+            (44, vec![3, 4, 4, 5], "moore,>=30"),
+        ] {
+            let rule: Rule = spec.parse().unwrap();
+            let grid = random_grid(seed, &shape);
+            for mode in [RemovalMode::Synchronous, RemovalMode::InPlace] {
+                let mut removed = grid.clone();
+                let mut swept = grid.clone();
+                let rounds = removal_rounds_nd(&mut removed, mode, &rule).unwrap();
+                assert!(!rounds.is_empty(), "{spec} {mode:?}");
+                assert_eq!(
+                    rounds,
+                    sweep_rounds_nd(&mut swept, mode, &rule),
+                    "{spec} {mode:?}"
+                );
+                assert_eq!(removed, swept);
+            }
+        }
+    }
+
+    #[test]
+    fn test_map_formats() {
+        let ragged = "@@.\n\n.@\n@@@@\n";
+        match read_map(&mut ragged.as_bytes()) {
+            Err(MapError::Ragged {
+                line,
+                width,
+                expected,
+            }) => assert_eq!((line, width, expected), (3, 2, 3)),
+            other => panic!("Expected a ragged row, got {other:?}"),
+        }
+
+        let (map, padded) = MapFormat::strict()
+            .lenient()
+            .read(&mut ragged.as_bytes())
+            .unwrap();
+        assert_eq!(map.dims(), (3, 4));
+        assert_eq!(
+            map.bales().collect::<Vec<_>>(),
+            [(0, 0), (0, 1), (1, 1), (2, 0), (2, 1), (2, 2), (2, 3)]
+        );
+        let warnings: Vec<String> = padded.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            warnings,
+            [
+                "Line 1: padded row of 3 cells to 4",
+                "Line 3: padded row of 2 cells to 4"
+            ]
+        );
+
+        let err = read_map(&mut "@@.\n.#@\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "Line 2, column 2: unknown glyph '#'");
+        let err = read_map(&mut "  @x".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "Line 1, column 4: unknown glyph 'x'");
+        let format = MapFormat::strict().with_legend("#=@, o=.").unwrap();
+        let (map, _) = format.read(&mut "@@o\n.#@\n".as_bytes()).unwrap();
+        assert_eq!(map.bales().count(), 4);
+        assert!(MapFormat::strict().with_legend("#@").is_err());
+        assert!(MapFormat::strict().with_legend("#=x").is_err());
+
+        assert!(matches!(
+            read_map(&mut "\n  \n".as_bytes()),
+            Err(MapError::Empty)
+        ));
+    }
+
+    #[test]
+    fn test_every_reader_uses_the_format() {
+        let err = read_sparse(b"@x@\n\n@@\n").unwrap_err();
+        assert_eq!(err.to_string(), "Line 1, column 2: unknown glyph 'x'");
+        let err = read_grid(b"@.\n\n @x\n").unwrap_err();
+        assert_eq!(err.to_string(), "Line 3, column 3: unknown glyph 'x'");
+        assert!(matches!(
+            read_grid(b"@.\n\n@@@\n"),
+            Err(MapError::Ragged { line: 3, .. })
+        ));
+        assert!(matches!(
+            read_grid(b"@.\n\n.@\n..\n"),
+            Err(MapError::UnevenSlices { .. })
+        ));
+
+        let format = MapFormat::strict()
+            .lenient()
+            .with_legend("#=@,o=.")
+            .unwrap();
+        let (sparse, padded) = format.read_sparse(&b"#o#\n\n##\n"[..]).unwrap();
+        assert_eq!(sparse.dims(), (2, 3));
+        assert_eq!(sparse.bales.len(), 4);
+        assert_eq!(padded[0].to_string(), "Line 3: padded row of 2 cells to 3");
+
+        let (grid, padded) = format.read_grid(&b"#o\n\n#\n"[..]).unwrap();
+        assert_eq!(grid.shape(), [2, 1, 2]);
+        assert_eq!(grid.iter().copied().collect::<Vec<_>>(), [1, 0, 1, 0]);
+        assert_eq!(padded.len(), 1);
     }
 }
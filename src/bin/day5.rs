@@ -1,113 +1,477 @@
 use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fmt,
     fs::File,
-    io::{BufRead as _, BufReader, Read},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    iter::Peekable,
 };
 
-use aoc2025::get_input;
+use anyhow::{Context as _, Result, anyhow, bail};
+use aoc2025::{flag_value, get_input, has_flag, init_logging};
+use bstr::ByteSlice as _;
 
+/// A range of IDs, including both ends.
+type IdRange = (u64, u64);
+
+/// Merges ranges, in any order, into a sorted list of disjoint, non-adjacent
+/// ranges. A range whose first ID is after its last is an error.
+fn merge_ranges(ranges: &[IdRange]) -> Result<Vec<IdRange>> {
+    if let Some(r) = ranges.iter().find(|r| r.0 > r.1) {
+        bail!("Range {}-{} ends before it starts", r.0, r.1);
+    }
+    let mut sorted = ranges.to_vec();
+    sorted.sort_unstable();
+    let mut combined: Vec<IdRange> = Vec::with_capacity(sorted.len());
+    for range in sorted {
+        match combined.last_mut() {
+            // Saturating, as nothing can follow a range ending at u64::MAX.
+            Some(latest) if range.0 <= latest.1.saturating_add(1) => {
+                latest.1 = latest.1.max(range.1);
+            }
+            _ => combined.push(range),
+        }
+    }
+    Ok(combined)
+}
+
+/// Whether `id` is in one of `merged`, which must be sorted and disjoint.
+fn in_ranges(merged: &[IdRange], id: u64) -> bool {
+    // The only range that can hold `id` is the last one starting at or before it.
+    let after = merged.partition_point(|r| r.0 <= id);
+    after > 0 && id <= merged[after - 1].1
+}
+
+/// The IDs in `from` that aren't in `remove`. Both must be merged, as from `merge_ranges`.
+fn subtract_ranges(from: &[IdRange], remove: &[IdRange]) -> Vec<IdRange> {
+    let mut left = Vec::new();
+    let mut remove = remove.iter().peekable();
+    for &(first, last) in from {
+        let mut next = Some(first);
+        // Skip removals that end before this range starts.
+        while remove.next_if(|r| r.1 < first).is_some() {}
+        while let Some(start) = next
+            && let Some(&&(r_first, r_last)) = remove.peek()
+            && r_first <= last
+        {
+            if start < r_first {
+                left.push((start, r_first - 1));
+            }
+            next = r_last.checked_add(1).filter(|&n| n <= last);
+            if r_last > last {
+                // This removal may reach into the next range too.
+                break;
+            }
+            remove.next();
+        }
+        if let Some(start) = next {
+            left.push((start, last));
+        }
+    }
+    left
+}
+
+/// How the fresh ranges changed from one stock to another.
+///
+/// Ranges are compared once merged. Merged ranges that overlap or touch are
+/// grouped together, so a range that grew, shrank, split or joined another is
+/// reported as a change from the ranges before to the ranges after.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct StockDiff {
+    became_fresh: Vec<IdRange>,
+    became_spoiled: Vec<IdRange>,
+    added: Vec<IdRange>,
+    removed: Vec<IdRange>,
+    changed: Vec<(Vec<IdRange>, Vec<IdRange>)>,
+    /// The change in `count_all_fresh`.
+    net_change: i128,
+}
+
+impl fmt::Display for StockDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |ranges: &[IdRange]| {
+            if ranges.is_empty() {
+                return "none".to_string();
+            }
+            let ranges: Vec<String> = ranges.iter().map(|r| format!("{}-{}", r.0, r.1)).collect();
+            ranges.join(", ")
+        };
+        writeln!(f, "Became fresh: {}", list(&self.became_fresh))?;
+        writeln!(f, "Became spoiled: {}", list(&self.became_spoiled))?;
+        writeln!(f, "Ranges added: {}", list(&self.added))?;
+        writeln!(f, "Ranges removed: {}", list(&self.removed))?;
+        for (before, after) in &self.changed {
+            writeln!(f, "Ranges changed: {} to {}", list(before), list(after))?;
+        }
+        write!(f, "Net change in fresh IDs: {:+}", self.net_change)
+    }
+}
+
+/// The two parts of a stock file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    FreshRanges,
+    AvailableIds,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Section::FreshRanges => "Fresh ranges",
+            Section::AvailableIds => "Available IDs",
+        })
+    }
+}
+
+impl Section {
+    /// The section a header line names, which can't go back to the ranges.
+    fn after_header(self, name: &str) -> Result<Section> {
+        let name = name.trim().to_lowercase();
+        if name.contains("fresh") || name.contains("range") {
+            if self == Section::AvailableIds {
+                bail!("the fresh ranges must come before the IDs");
+            }
+            Ok(Section::FreshRanges)
+        } else if name.contains("available") || name.contains("id") || name.contains("ingredient") {
+            Ok(Section::AvailableIds)
+        } else {
+            bail!("unknown section {name:?}");
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    Range(IdRange),
+    Id(u64),
+}
+
+/// Reads the entries of a stock file one line at a time.
+///
+/// Blank lines and anything after a `#` are ignored, so ranges may be spread
+/// out and IDs may be followed by blank lines. The ranges come first, and the
+/// first plain ID starts the ID section. Either section may open with a
+/// header such as `Fresh ranges:` or `[available]`. Errors name the section
+/// and line they were found on.
+struct StockEntries<R> {
+    rdr: R,
+    line: Vec<u8>,
+    line_num: usize,
+    section: Section,
+}
+
+impl<R: BufRead> StockEntries<R> {
+    fn new(rdr: R) -> Self {
+        StockEntries {
+            rdr,
+            line: Vec::new(),
+            line_num: 0,
+            section: Section::FreshRanges,
+        }
+    }
+
+    /// Reads the next entry, or returns None at the end of the input.
+    fn read_entry(&mut self) -> Result<Option<Entry>> {
+        loop {
+            self.line.clear();
+            if self.rdr.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(None);
+            }
+            self.line_num += 1;
+            let text = self
+                .line
+                .to_str()
+                .map_err(|_| anyhow!("the line isn't UTF-8"))?;
+            let text = text.split('#').next().unwrap_or_default().trim();
+            if text.is_empty() {
+                continue;
+            }
+            if let Some(name) = (text.strip_suffix(':'))
+                .or_else(|| text.strip_prefix('[').and_then(|t| t.strip_suffix(']')))
+            {
+                self.section = self.section.after_header(name)?;
+                continue;
+            }
+            return Ok(Some(match text.split_once('-') {
+                Some((first, last)) => {
+                    if self.section == Section::AvailableIds {
+                        bail!("expected an ID, found the range {text:?}");
+                    }
+                    let parse = |id: &str| {
+                        (id.trim().parse::<u64>()).with_context(|| format!("bad range {text:?}"))
+                    };
+                    let range = (parse(first)?, parse(last)?);
+                    if range.0 > range.1 {
+                        bail!("range {text} ends before it starts");
+                    }
+                    Entry::Range(range)
+                }
+                None => {
+                    self.section = Section::AvailableIds;
+                    Entry::Id((text.parse()).with_context(|| format!("bad ID {text:?}"))?)
+                }
+            }));
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for StockEntries<R> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry()
+            .with_context(|| format!("{}, line {}", self.section, self.line_num))
+            .transpose()
+    }
+}
+
+/// Reads the fresh ranges from the start of `entries`, leaving the IDs.
+fn read_fresh_ranges<R: BufRead>(entries: &mut Peekable<StockEntries<R>>) -> Result<Vec<IdRange>> {
+    let mut fresh_ranges = Vec::new();
+    while let Some(entry) = entries.next_if(|e| !matches!(e, Ok(Entry::Id(_)))) {
+        match entry? {
+            Entry::Range(range) => fresh_ranges.push(range),
+            Entry::Id(_) => unreachable!(),
+        }
+    }
+    Ok(fresh_ranges)
+}
+
+/// How many streamed IDs were fresh and how many spoiled.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Classified {
+    fresh: u64,
+    spoiled: u64,
+}
+
+/// Decides whether each ID left in `entries` is fresh, one line at a time, so
+/// the IDs never need to be held in memory. With `out`, each ID is written
+/// back followed by "fresh" or "spoiled".
+fn classify_ids<I: Iterator<Item = Result<Entry>>, W: Write>(
+    entries: I,
+    merged: &[IdRange],
+    mut out: Option<W>,
+) -> Result<Classified> {
+    let mut classified = Classified::default();
+    for entry in entries {
+        let Entry::Id(id) = entry? else {
+            bail!("Found a range among the IDs");
+        };
+        let fresh = in_ranges(merged, id);
+        if fresh {
+            classified.fresh += 1;
+        } else {
+            classified.spoiled += 1;
+        }
+        if let Some(out) = out.as_mut() {
+            writeln!(out, "{id} {}", if fresh { "fresh" } else { "spoiled" })?;
+        }
+    }
+    if let Some(out) = out.as_mut() {
+        out.flush()?;
+    }
+    Ok(classified)
+}
+
+#[derive(Debug)]
 struct Stock {
-    fresh_ranges: Vec<(u64, u64)>,
+    fresh_ranges: Vec<IdRange>,
     available: Vec<u64>,
 }
 
 impl Stock {
-    fn from_rdr<R: Read>(rdr: &mut R) -> Self {
-        let mut fresh_ranges = Vec::new();
+    fn from_rdr<R: Read>(rdr: &mut R) -> Result<Self> {
+        let mut entries = StockEntries::new(BufReader::new(rdr)).peekable();
+        let mut fresh_ranges = read_fresh_ranges(&mut entries)?;
         let mut available = Vec::new();
-        let lrdr = BufReader::new(rdr);
-        let mut line_iter = lrdr.lines();
-
-        for line in &mut line_iter {
-            let line = line.unwrap();
-            let range = line.trim();
-            if range.len() == 0 {
-                if fresh_ranges.len() > 0 {
-                    break;
-                } else {
-                    continue;
-                }
-            }
-            let first_last = range.split_once("-").unwrap();
-            let (first, last) = (
-                first_last.0.parse::<u64>().unwrap(),
-                first_last.1.parse::<u64>().unwrap(),
-            );
-            fresh_ranges.push((first, last));
-        }
-
-        for line in &mut line_iter {
-            let line = line.unwrap();
-            let iid = line.trim();
-            let ingredient_id = iid.parse::<u64>().unwrap();
+        for entry in entries {
+            let Entry::Id(ingredient_id) = entry? else {
+                unreachable!("StockEntries rejects ranges after IDs");
+            };
             available.push(ingredient_id);
         }
 
         fresh_ranges.sort_by_key(|r| r.0);
 
-        Self {
+        Ok(Self {
             fresh_ranges,
             available,
-        }
+        })
     }
 
-    fn count_fresh(&self) -> usize {
-        let mut num_fresh = 0;
-        for &id in self.available.iter() {
-            let fresh = self.fresh_ranges.iter().any(|r| id >= r.0 && id <= r.1);
-            if fresh {
-                num_fresh += 1;
+    /// Counts the available IDs that are fresh, merging the ranges once and
+    /// then binary-searching them for each ID.
+    fn count_fresh(&self) -> Result<usize> {
+        let merged = merge_ranges(&self.fresh_ranges)?;
+        Ok(self
+            .available
+            .iter()
+            .filter(|&&id| in_ranges(&merged, id))
+            .count())
+    }
+
+    /// Pairs each fresh available ID with the ranges that say it is fresh, in
+    /// sorted order. Run this before `combine_fresh_ranges` to see
+    /// the ranges as they were given.
+    ///
+    /// The IDs are sorted and walked alongside the ranges, keeping the ranges
+    /// that have started and not yet ended in a heap.
+    fn fresh_matches(&self) -> Vec<(u64, Vec<IdRange>)> {
+        let mut order: Vec<usize> = (0..self.available.len()).collect();
+        order.sort_by_key(|&i| self.available[i]);
+        let mut matches = vec![Vec::new(); self.available.len()];
+        // Sorted here rather than assumed, so the ranges may be in any order.
+        let mut by_start: Vec<&IdRange> = self.fresh_ranges.iter().collect();
+        by_start.sort_by_key(|r| r.0);
+        let mut ranges = by_start.into_iter().peekable();
+        let mut active = BinaryHeap::new();
+        for i in order {
+            let id = self.available[i];
+            while let Some(&range) = ranges.next_if(|r| r.0 <= id) {
+                active.push(Reverse((range.1, range.0)));
+            }
+            while active.peek().is_some_and(|&Reverse((last, _))| last < id) {
+                active.pop();
             }
+            let mut found: Vec<IdRange> = active
+                .iter()
+                .map(|&Reverse((last, first))| (first, last))
+                .collect();
+            found.sort_unstable();
+            matches[i] = found;
         }
-        num_fresh
+        self.available
+            .iter()
+            .zip(matches)
+            .filter(|(_, found)| !found.is_empty())
+            .map(|(&id, found)| (id, found))
+            .collect()
     }
 
-    fn combine_fresh_ranges(&mut self) {
-        let mut combined = Vec::with_capacity(self.fresh_ranges.len());
-        // This requires fresh_ranges to be sorted by starting ID, which it now is.
-        combined.push(self.fresh_ranges[0]);
-        for range in &self.fresh_ranges[1..] {
-            let latest = combined.last_mut().unwrap();
-            if range.0 <= latest.1 + 1 {
-                if range.1 > latest.1 {
-                    latest.1 = range.1;
-                }
+    fn combine_fresh_ranges(&mut self) -> Result<()> {
+        self.fresh_ranges = merge_ranges(&self.fresh_ranges)?;
+        Ok(())
+    }
+
+    /// Counts the IDs in any fresh range, which can be more than fit in a u64.
+    fn count_all_fresh(&self) -> Result<u128> {
+        let merged = merge_ranges(&self.fresh_ranges)?;
+        Ok(merged.iter().map(|r| (r.1 - r.0) as u128 + 1).sum())
+    }
+
+    /// How the fresh ranges changed from this stock to `newer`.
+    fn diff(&self, newer: &Stock) -> Result<StockDiff> {
+        let before = merge_ranges(&self.fresh_ranges)?;
+        let after = merge_ranges(&newer.fresh_ranges)?;
+        let mut diff = StockDiff {
+            became_fresh: subtract_ranges(&after, &before),
+            became_spoiled: subtract_ranges(&before, &after),
+            net_change: newer.count_all_fresh()? as i128 - self.count_all_fresh()? as i128,
+            ..Default::default()
+        };
+
+        // Walk both lists in order, gathering ranges that overlap or touch.
+        let mut tagged: Vec<(IdRange, bool)> = before.iter().map(|&r| (r, false)).collect();
+        tagged.extend(after.iter().map(|&r| (r, true)));
+        tagged.sort_unstable();
+        let mut groups: Vec<(Vec<IdRange>, Vec<IdRange>)> = Vec::new();
+        let mut group_last: u64 = 0;
+        for (range, is_after) in tagged {
+            if groups.is_empty() || range.0 > group_last.saturating_add(1) {
+                groups.push(Default::default());
+                group_last = range.1;
+            }
+            let group = groups.last_mut().unwrap();
+            if is_after {
+                group.1.push(range);
             } else {
-                combined.push(*range);
+                group.0.push(range);
             }
+            group_last = group_last.max(range.1);
         }
-        self.fresh_ranges = std::mem::take(&mut combined);
-    }
-
-    fn count_all_fresh(&self) -> u64 {
-        self.fresh_ranges.iter().map(|r| r.1 - r.0 + 1).sum()
+        for group in groups {
+            match group {
+                (before, after) if before == after => (),
+                (before, after) if before.is_empty() => diff.added.extend(after),
+                (before, after) if after.is_empty() => diff.removed.extend(before),
+                changed => diff.changed.push(changed),
+            }
+        }
+        Ok(diff)
     }
 
     fn _debug_fresh_ranges(&self) {
         for r in &self.fresh_ranges {
-            println!("{} - {}", r.0, r.1);
+            log::debug!("{} - {}", r.0, r.1);
         }
     }
 }
 
-fn main() {
+fn main() -> Result<()> {
+    init_logging();
+    // Compare two stock files with `--diff OLD NEW`.
+    let mut args = std::env::args().skip_while(|arg| arg != "--diff").skip(1);
+    if let Some(old_path) = args.next() {
+        let Some(new_path) = args.next() else {
+            bail!("--diff needs an old and a new stock file");
+        };
+        let read = |path: &str| -> Result<Stock> {
+            let mut in_file = File::open(path).with_context(|| format!("Opening {path}"))?;
+            Stock::from_rdr(&mut in_file).with_context(|| format!("In {path}"))
+        };
+        let diff = read(&old_path)?.diff(&read(&new_path)?)?;
+        println!("Day 5 changes from {old_path} to {new_path}:\n{diff}");
+        return Ok(());
+    }
+
+    // Classify the IDs of a stock file too big to hold with `--stream FILE`,
+    // writing each one out with `--classified OUT`.
+    if let Some(path) = flag_value("--stream") {
+        let in_file = File::open(&path).with_context(|| format!("Opening {path}"))?;
+        let mut entries = StockEntries::new(BufReader::new(in_file)).peekable();
+        let merged = merge_ranges(&read_fresh_ranges(&mut entries)?)?;
+        let out = match flag_value("--classified") {
+            Some(out_path) => Some(BufWriter::new(
+                File::create(&out_path).with_context(|| format!("Creating {out_path}"))?,
+            )),
+            None => None,
+        };
+        let classified = classify_ids(entries, &merged, out)?;
+        println!(
+            "Day 5 streamed {path}: {} fresh and {} spoiled ingredients.",
+            classified.fresh, classified.spoiled
+        );
+        return Ok(());
+    }
+
     let in_path = get_input("input_d5.txt");
     let mut in_file = File::open(&in_path).unwrap();
-    let mut stock = Stock::from_rdr(&mut in_file);
+    let mut stock = Stock::from_rdr(&mut in_file)?;
     {
-        let nf = stock.count_fresh();
+        let nf = stock.count_fresh()?;
         println!("Day 5 pt 1: There are {} fresh ingredients.", nf);
+        // List the ranges behind each fresh ID with `--audit`.
+        if has_flag("--audit") {
+            for (id, ranges) in stock.fresh_matches() {
+                let ranges: Vec<String> =
+                    ranges.iter().map(|r| format!("{}-{}", r.0, r.1)).collect();
+                println!("{id}: {}", ranges.join(", "));
+            }
+        }
     }
 
     {
-        stock.combine_fresh_ranges();
-        let all_fresh = stock.count_all_fresh();
+        stock.combine_fresh_ranges()?;
+        let all_fresh = stock.count_all_fresh()?;
         println!("Day 5 pt 2: {} total possible fresh IDs", all_fresh);
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aoc2025::Lcg;
 
     static INPUT: &str = r#"
 3-5
@@ -125,31 +489,257 @@ mod tests {
 
     fn get_test_stock() -> Stock {
         let mut input_file = std::io::Cursor::new(INPUT.trim().to_owned());
-        Stock::from_rdr(&mut input_file)
+        Stock::from_rdr(&mut input_file).unwrap()
     }
 
     #[test]
     fn test_pt1() {
         let stock = get_test_stock();
-        assert_eq!(stock.count_fresh(), 3);
+        assert_eq!(stock.count_fresh().unwrap(), 3);
     }
 
     #[test]
     fn test_pt2() {
         let mut stock = get_test_stock();
-        stock.combine_fresh_ranges();
-        assert_eq!(stock.count_fresh(), 3); // Same as pt1
+        stock.combine_fresh_ranges().unwrap();
+        assert_eq!(stock.count_fresh().unwrap(), 3); // Same as pt1
         assert_eq!(stock.fresh_ranges, vec![(3, 5), (10, 20)]);
-        assert_eq!(stock.count_all_fresh(), 14);
+        assert_eq!(stock.count_all_fresh().unwrap(), 14);
     }
 
     #[test]
     fn test_combined() {
         let in_path = get_input("input_d5.txt");
         let mut in_file = File::open(&in_path).unwrap();
-        let mut stock = Stock::from_rdr(&mut in_file);
-        assert_eq!(stock.count_fresh(), 652);
-        stock.combine_fresh_ranges();
-        assert_eq!(stock.count_fresh(), 652);
+        let mut stock = Stock::from_rdr(&mut in_file).unwrap();
+        assert_eq!(stock.count_fresh().unwrap(), 652);
+        stock.combine_fresh_ranges().unwrap();
+        assert_eq!(stock.count_fresh().unwrap(), 652);
+    }
+
+    #[test]
+    fn test_fresh_matches() {
+        let stock = get_test_stock();
+        assert_eq!(
+            stock.fresh_matches(),
+            vec![
+                (5, vec![(3, 5)]),
+                (11, vec![(10, 14)]),
+                (17, vec![(12, 18), (16, 20)]),
+            ]
+        );
+        let mut reversed = get_test_stock();
+        reversed.fresh_ranges.reverse();
+        assert_eq!(reversed.fresh_matches(), stock.fresh_matches());
+
+        let in_path = get_input("input_d5.txt");
+        let mut in_file = File::open(&in_path).unwrap();
+        let stock = Stock::from_rdr(&mut in_file).unwrap();
+        let matches = stock.fresh_matches();
+        assert_eq!(matches.len(), 652);
+        for (id, ranges) in matches {
+            let mut expected: Vec<IdRange> = (stock.fresh_ranges.iter())
+                .filter(|r| r.0 <= id && id <= r.1)
+                .copied()
+                .collect();
+            expected.sort_unstable();
+            assert_eq!(ranges, expected);
+        }
+    }
+
+    #[test]
+    fn test_in_ranges() {
+        let merged = [(3, 5), (10, 20), (22, 22)];
+        let fresh: Vec<u64> = (0..25).filter(|&id| in_ranges(&merged, id)).collect();
+        assert_eq!(
+            fresh,
+            [3, 4, 5, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 22]
+        );
+        assert!(!in_ranges(&[], 0));
+    }
+
+    /// Random ranges within `span` IDs of `base`, some of them single IDs.
+    fn random_ranges(rng: &mut Lcg, base: u64, span: u64) -> Vec<IdRange> {
+        let mut next = || rng.next_u32() as u64;
+        let num_ranges = next() % 12;
+        (0..num_ranges)
+            .map(|_| {
+                let first = next() % span;
+                let last = (first + next() % 20 * (next() % 2)).min(span - 1);
+                (base + first, base + last)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_matches_naive() {
+        let mut rng = Lcg::new(44);
+        let span = 200;
+        for base in [0, 1 << 40, u64::MAX - (span - 1)] {
+            for _ in 0..300 {
+                let ranges = random_ranges(&mut rng, base, span);
+                let merged = merge_ranges(&ranges).unwrap();
+                for pair in merged.windows(2) {
+                    assert!(pair[0].1 + 1 < pair[1].0, "{merged:?} from {ranges:?}");
+                }
+                let naive: Vec<u64> = (base..=base + (span - 1))
+                    .filter(|&id| ranges.iter().any(|r| r.0 <= id && id <= r.1))
+                    .collect();
+                let fast: Vec<u64> = (base..=base + (span - 1))
+                    .filter(|&id| in_ranges(&merged, id))
+                    .collect();
+                assert_eq!(fast, naive, "{ranges:?}");
+
+                let stock = Stock {
+                    fresh_ranges: ranges,
+                    available: (base..=base + (span - 1)).collect(),
+                };
+                assert_eq!(stock.count_all_fresh().unwrap(), naive.len() as u128);
+                assert_eq!(stock.count_fresh().unwrap(), naive.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_edge_cases() {
+        let mut stock = Stock {
+            fresh_ranges: vec![],
+            available: vec![0, u64::MAX],
+        };
+        stock.combine_fresh_ranges().unwrap();
+        assert_eq!(stock.count_fresh().unwrap(), 0);
+        assert_eq!(stock.count_all_fresh().unwrap(), 0);
+
+        stock.fresh_ranges = vec![(u64::MAX, u64::MAX), (5, u64::MAX - 1), (0, 4)];
+        stock.combine_fresh_ranges().unwrap();
+        assert_eq!(stock.fresh_ranges, vec![(0, u64::MAX)]);
+        assert_eq!(stock.count_all_fresh().unwrap(), 1 << 64);
+        assert_eq!(stock.count_fresh().unwrap(), 2);
+
+        stock.fresh_ranges = vec![(3, 5), (9, 8)];
+        let err = stock.combine_fresh_ranges().unwrap_err();
+        assert_eq!(err.to_string(), "Range 9-8 ends before it starts");
+        assert!(stock.count_fresh().is_err());
+        assert!(stock.count_all_fresh().is_err());
+    }
+
+    #[test]
+    fn test_subtract_matches_naive() {
+        let mut rng = Lcg::new(45);
+        let span = 150;
+        for base in [0, u64::MAX - (span - 1)] {
+            for _ in 0..300 {
+                let from = merge_ranges(&random_ranges(&mut rng, base, span)).unwrap();
+                let remove = merge_ranges(&random_ranges(&mut rng, base, span)).unwrap();
+                let left = subtract_ranges(&from, &remove);
+                let naive: Vec<u64> = (base..=base + (span - 1))
+                    .filter(|&id| in_ranges(&from, id) && !in_ranges(&remove, id))
+                    .collect();
+                let expected = merge_ranges(&naive.iter().map(|&id| (id, id)).collect::<Vec<_>>());
+                assert_eq!(left, expected.unwrap(), "{from:?} - {remove:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = get_test_stock();
+        let new = Stock {
+            fresh_ranges: vec![(3, 5), (10, 12), (15, 22), (30, 31), (40, 40)],
+            available: vec![],
+        };
+        let diff = old.diff(&new).unwrap();
+        assert_eq!(
+            diff,
+            StockDiff {
+                became_fresh: vec![(21, 22), (30, 31), (40, 40)],
+                became_spoiled: vec![(13, 14)],
+                added: vec![(30, 31), (40, 40)],
+                removed: vec![],
+                changed: vec![(vec![(10, 20)], vec![(10, 12), (15, 22)])],
+                net_change: 3,
+            }
+        );
+        assert_eq!(
+            diff.to_string().lines().last(),
+            Some("Net change in fresh IDs: +3")
+        );
+        assert_eq!(new.diff(&old).unwrap().removed, vec![(30, 31), (40, 40)]);
+        assert_eq!(old.diff(&old).unwrap(), StockDiff::default());
+    }
+
+    #[test]
+    fn test_classify_ids() {
+        let mut entries = StockEntries::new(INPUT.trim().as_bytes()).peekable();
+        let merged = merge_ranges(&read_fresh_ranges(&mut entries).unwrap()).unwrap();
+        let mut out = Vec::new();
+        let classified = classify_ids(entries, &merged, Some(&mut out)).unwrap();
+        assert_eq!(
+            classified,
+            Classified {
+                fresh: 3,
+                spoiled: 3
+            }
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "1 spoiled\n5 fresh\n8 spoiled\n11 fresh\n17 fresh\n32 spoiled\n"
+        );
+
+        let in_path = get_input("input_d5.txt");
+        let in_file = BufReader::new(File::open(&in_path).unwrap());
+        let mut entries = StockEntries::new(in_file).peekable();
+        let merged = merge_ranges(&read_fresh_ranges(&mut entries).unwrap()).unwrap();
+        let classified = classify_ids(entries, &merged, None::<Vec<u8>>).unwrap();
+        assert_eq!(classified.fresh, 652);
+
+        let entries = StockEntries::new("5\n1x\n".as_bytes());
+        let err = classify_ids(entries, &merged, None::<Vec<u8>>).unwrap_err();
+        assert_eq!(err.to_string(), "Available IDs, line 2");
+    }
+
+    #[test]
+    fn test_sections() {
+        let input = "Fresh ranges:\r\n3-5\r\n\r\n10-14 # the first batch\r\n\r\n\r\n16 - 20\r\n# 12-18\r\n12-18\r\n\r\n\r\n[Available IDs]\r\n1\r\n\r\n5\r\n8\r\n11\r\n17\r\n32\r\n  \r\n\r\n";
+        let stock = Stock::from_rdr(&mut input.as_bytes()).unwrap();
+        let plain = get_test_stock();
+        assert_eq!(stock.fresh_ranges, plain.fresh_ranges);
+        assert_eq!(stock.available, plain.available);
+
+        // Without headers, the first plain ID starts the ID section.
+        let stock = Stock::from_rdr(&mut "\n\n3-5\n\n10-14\n1\n2\n".as_bytes()).unwrap();
+        assert_eq!(stock.fresh_ranges, vec![(3, 5), (10, 14)]);
+        assert_eq!(stock.available, vec![1, 2]);
+
+        let error =
+            |input: &str| format!("{:#}", Stock::from_rdr(&mut input.as_bytes()).unwrap_err());
+        assert_eq!(
+            error("3-5\n1\n\n10-14\n"),
+            "Available IDs, line 4: expected an ID, found the range \"10-14\""
+        );
+        assert_eq!(
+            error("3-5\n3-x\n"),
+            "Fresh ranges, line 2: bad range \"3-x\": invalid digit found in string"
+        );
+        assert_eq!(
+            error("3-5\n\n1\n4.5\n"),
+            "Available IDs, line 4: bad ID \"4.5\": invalid digit found in string"
+        );
+        assert_eq!(
+            error("3-5\nx\n"),
+            "Available IDs, line 2: bad ID \"x\": invalid digit found in string"
+        );
+        assert_eq!(
+            error("9-8\n"),
+            "Fresh ranges, line 1: range 9-8 ends before it starts"
+        );
+        assert_eq!(
+            error("3-5\n[Spoiled]\n"),
+            "Fresh ranges, line 2: unknown section \"spoiled\""
+        );
+        assert_eq!(
+            error("[ids]\n1\nFresh:\n3-5\n"),
+            "Available IDs, line 3: the fresh ranges must come before the IDs"
+        );
     }
 }
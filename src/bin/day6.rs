@@ -1,31 +1,193 @@
-use aoc2025::get_input_string;
+use anyhow::{Context as _, Result, anyhow};
+use aoc2025::{get_input_string, init_logging, map_items, run_compared};
+use std::fmt;
+use std::str::FromStr;
 
-fn main() {
+/// Exact answers with the `bigint` feature, overflow errors without it.
+#[cfg(feature = "bigint")]
+type Answer = num_bigint::BigInt;
+#[cfg(not(feature = "bigint"))]
+type Answer = i64;
+
+fn main() -> Result<()> {
+    init_logging();
     let input_str = get_input_string("input_d6.txt").unwrap();
     {
-        let homework = Homework::new_from(&input_str);
-        let answers = homework.compute_all();
-        let grand_total: i64 = answers.iter().sum();
+        let homework = Homework::<Answer>::new_from(&input_str)?;
+        let answers: Vec<Answer> =
+            run_compared("Day 6 Part 1", |parallel| homework.compute_all(parallel))?;
+        let grand_total = grand_total(&answers)?;
         println!("Day 6 Part 1: grand total: {}", grand_total);
     }
 
     {
-        let homework = Homework::from_cephalopod(&input_str);
-        let answers = homework.compute_all();
-        let grand_total: i64 = answers.iter().sum();
+        let homework = Homework::<Answer>::from_cephalopod(&input_str)?;
+        let answers: Vec<Answer> =
+            run_compared("Day 6 Part 2", |parallel| homework.compute_all(parallel))?;
+        let grand_total = grand_total(&answers)?;
         println!("Day 6 Part 2: grand total: {}", grand_total);
     }
+    Ok(())
 }
 
-struct Homework {
-    cols: Vec<Vec<i64>>,
+struct Homework<N> {
+    cols: Vec<Vec<N>>,
     opers: Vec<Oper>,
+    /// Whether the problems were read from the right-hand end of the sheet.
+    right_to_left: bool,
 }
 
+/// The arithmetic the operators need, with None on overflow.
+trait Number:
+    Clone
+    + Ord
+    + fmt::Debug
+    + fmt::Display
+    + FromStr<Err: std::error::Error + Send + Sync + 'static>
+    + Send
+    + Sync
+{
+    fn from_i64(n: i64) -> Self;
+    fn try_add(&self, other: &Self) -> Option<Self>;
+    fn try_sub(&self, other: &Self) -> Option<Self>;
+    fn try_mul(&self, other: &Self) -> Option<Self>;
+    /// Divides by a non-zero number, rounding towards zero.
+    fn try_div(&self, other: &Self) -> Option<Self>;
+    fn try_pow(&self, exp: u32) -> Option<Self>;
+    fn to_u32(&self) -> Option<u32>;
+}
+
+impl Number for i64 {
+    fn from_i64(n: i64) -> Self {
+        n
+    }
+
+    fn try_add(&self, other: &Self) -> Option<Self> {
+        self.checked_add(*other)
+    }
+
+    fn try_sub(&self, other: &Self) -> Option<Self> {
+        self.checked_sub(*other)
+    }
+
+    fn try_mul(&self, other: &Self) -> Option<Self> {
+        self.checked_mul(*other)
+    }
+
+    fn try_div(&self, other: &Self) -> Option<Self> {
+        self.checked_div(*other)
+    }
+
+    fn try_pow(&self, exp: u32) -> Option<Self> {
+        self.checked_pow(exp)
+    }
+
+    fn to_u32(&self) -> Option<u32> {
+        u32::try_from(*self).ok()
+    }
+}
+
+/// The most bits a power may need before `BigInt` gives up on it, about
+/// 300,000 decimal digits.
+#[cfg(feature = "bigint")]
+const MAX_POW_BITS: u64 = 1 << 20;
+
+/// Exact arithmetic, except for powers that would be larger than `MAX_POW_BITS`.
+#[cfg(feature = "bigint")]
+impl Number for num_bigint::BigInt {
+    fn from_i64(n: i64) -> Self {
+        n.into()
+    }
+
+    fn try_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn try_sub(&self, other: &Self) -> Option<Self> {
+        Some(self - other)
+    }
+
+    fn try_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn try_div(&self, other: &Self) -> Option<Self> {
+        Some(self / other)
+    }
+
+    fn try_pow(&self, exp: u32) -> Option<Self> {
+        // Powers of 0, 1 and -1 stay small, and the rest need at most this many bits.
+        if self.magnitude() > &1u32.into() && self.bits() * exp as u64 > MAX_POW_BITS {
+            return None;
+        }
+        Some(self.pow(exp))
+    }
+
+    fn to_u32(&self) -> Option<u32> {
+        u32::try_from(self).ok()
+    }
+}
+
+/// An operator, applied to a problem's numbers in the order they are read:
+/// top to bottom normally, and right to left in the cephalopod layout.
 #[derive(Debug)]
 enum Oper {
     Plus,
     Mult,
+    /// The first number minus the rest.
+    Minus,
+    /// The first number divided by each of the rest, rounding down each time.
+    Div,
+    Min,
+    Max,
+    /// The digits of each number written one after another, so 12 || 3 is 123.
+    Concat,
+    /// Raises to a power, grouping from the right as usual, so 2 ^ 3 ^ 2 is 2 ^ 9.
+    Pow,
+}
+
+/// Why an operator couldn't be applied to a problem's numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OperError {
+    DivideByZero,
+    NegativeExponent(String),
+    NegativeConcat(String),
+    Overflow,
+}
+
+impl fmt::Display for OperError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperError::DivideByZero => write!(f, "division by zero"),
+            OperError::NegativeExponent(n) => write!(f, "negative exponent {n}"),
+            OperError::NegativeConcat(n) => write!(f, "can't concatenate negative number {n}"),
+            OperError::Overflow => write!(f, "overflow"),
+        }
+    }
+}
+
+impl std::error::Error for OperError {}
+
+/// An operator failing on one problem, numbered from 1 at the left of the sheet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProblemError {
+    column: usize,
+    error: OperError,
+}
+
+impl fmt::Display for ProblemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Problem {} failed: {}", self.column, self.error)
+    }
+}
+
+impl std::error::Error for ProblemError {}
+
+/// Adds up the answers, which can overflow even when none of them do.
+fn grand_total<N: Number>(answers: &[N]) -> Result<N, OperError> {
+    answers.iter().try_fold(N::from_i64(0), |acc, n| {
+        acc.try_add(n).ok_or(OperError::Overflow)
+    })
 }
 
 impl Oper {
@@ -33,20 +195,78 @@ impl Oper {
         match word {
             "+" => Some(Self::Plus),
             "*" => Some(Self::Mult),
+            "-" => Some(Self::Minus),
+            "/" => Some(Self::Div),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "||" => Some(Self::Concat),
+            "^" => Some(Self::Pow),
             _ => None,
         }
     }
 
-    fn operate_on(&self, numbers: &[i64]) -> i64 {
+    fn operate_on<N: Number>(&self, numbers: &[N]) -> Result<N, OperError> {
+        let zero = N::from_i64(0);
+        let Some((first, rest)) = numbers.split_first() else {
+            return Ok(zero);
+        };
+        let overflow = |n: Option<N>| n.ok_or(OperError::Overflow);
         match self {
-            Self::Plus => numbers.iter().sum::<i64>(),
-            Self::Mult => numbers.iter().product::<i64>(),
+            Self::Plus => rest
+                .iter()
+                .try_fold(first.clone(), |acc, n| overflow(acc.try_add(n))),
+            Self::Mult => rest
+                .iter()
+                .try_fold(first.clone(), |acc, n| overflow(acc.try_mul(n))),
+            Self::Minus => rest
+                .iter()
+                .try_fold(first.clone(), |acc, n| overflow(acc.try_sub(n))),
+            Self::Div => rest.iter().try_fold(first.clone(), |acc, n| {
+                if *n == zero {
+                    return Err(OperError::DivideByZero);
+                }
+                let quot = overflow(acc.try_div(n))?;
+                // Round down rather than towards zero.
+                let exact = overflow(quot.try_mul(n))? == acc;
+                if !exact && (acc < zero) != (*n < zero) {
+                    overflow(quot.try_sub(&N::from_i64(1)))
+                } else {
+                    Ok(quot)
+                }
+            }),
+            Self::Min => Ok(numbers.iter().min().unwrap().clone()),
+            Self::Max => Ok(numbers.iter().max().unwrap().clone()),
+            Self::Concat => numbers.iter().try_fold(zero.clone(), |acc, n| {
+                if *n < zero {
+                    return Err(OperError::NegativeConcat(n.to_string()));
+                }
+                let shift = overflow(N::from_i64(10).try_pow(n.to_string().len() as u32))?;
+                overflow(acc.try_mul(&shift).and_then(|acc| acc.try_add(n)))
+            }),
+            Self::Pow => {
+                let (last, bases) = numbers.split_last().unwrap();
+                bases.iter().rev().try_fold(last.clone(), |exp, base| {
+                    if exp < zero {
+                        return Err(OperError::NegativeExponent(exp.to_string()));
+                    }
+                    overflow(exp.to_u32().and_then(|exp| base.try_pow(exp)))
+                })
+            }
         }
     }
 }
 
-impl Homework {
-    fn new_from(input_str: &str) -> Self {
+fn parse_number<N: Number>(word: &str) -> Result<N> {
+    word.parse()
+        .with_context(|| format!("Bad number {:?}", word))
+}
+
+fn parse_oper(word: &str) -> Result<Oper> {
+    Oper::from_str(word).ok_or_else(|| anyhow!("Unknown operator {:?}", word))
+}
+
+impl<N: Number> Homework<N> {
+    fn new_from(input_str: &str) -> Result<Self> {
         let mut rows = Vec::new();
         let mut opers = Vec::new();
         let tinput = input_str.trim();
@@ -54,55 +274,57 @@ impl Homework {
         for (row, line) in tinput.lines().enumerate() {
             let words = line.split_whitespace();
             if row < num_lines - 1 {
-                let nums: Vec<i64> = words.map(|w| w.parse::<i64>().unwrap()).collect();
+                let nums: Vec<N> = words.map(parse_number).collect::<Result<_>>()?;
                 rows.push(nums);
             } else {
-                opers.extend(words.map(|w| Oper::from_str(w).unwrap()));
+                for word in words {
+                    opers.push(parse_oper(word)?);
+                }
             }
         }
 
         // Transpose rows into cols
         let mut cols = Vec::with_capacity(opers.len());
-        let mut col: Vec<i64> = Vec::new();
+        let mut col: Vec<N> = Vec::new();
         let mut row_iters: Vec<_> = rows.iter().map(|r| r.iter()).collect();
         loop {
             col.reserve(num_lines - 1);
             for riter in row_iters.iter_mut() {
                 if let Some(val) = riter.next() {
-                    col.push(*val);
+                    col.push(val.clone());
                 }
             }
-            if col.len() > 0 {
+            if !col.is_empty() {
                 cols.push(std::mem::take(&mut col));
             } else {
                 break;
             }
         }
-        Self { cols, opers }
+        Ok(Self {
+            cols,
+            opers,
+            right_to_left: false,
+        })
     }
 
-    fn from_cephalopod(input_str: &str) -> Self {
+    fn from_cephalopod(input_str: &str) -> Result<Self> {
         let mut cols = Vec::new();
-        let tinput = input_str.trim_matches(&['\r', '\n']); // Should handle Unix & DOS
+        let tinput = input_str.trim_matches(['\r', '\n']); // Should handle Unix & DOS
         let num_lines = tinput.lines().count();
-        println!("tinput: {} ({} lines)", tinput, num_lines);
+        log::debug!("Cephalopod sheet with {} lines:\n{}", num_lines, tinput);
         let mut row_iter = tinput.lines();
         let row_strs: Vec<Vec<char>> = (&mut row_iter)
             .take(num_lines - 1)
             .map(|l| l.chars().collect())
             .collect();
-        for rs in row_strs.iter() {
-            println!("Row: {:?}", rs);
-        }
         let mut opers: Vec<Oper> = row_iter
             .next()
             .unwrap()
-            .trim()
             .split_whitespace()
-            .map(|w| Oper::from_str(w).unwrap())
-            .collect();
+            .map(parse_oper)
+            .collect::<Result<_>>()?;
         opers.reverse();
-        println!("Opers: {:?}", opers);
+        log::debug!("Operators, right to left: {:?}", opers);
         let mut row_nums = Vec::new();
         let mut row_chars: Vec<_> = row_strs.iter().map(|r| r.iter().rev()).collect();
         row_nums.clear();
@@ -112,27 +334,44 @@ impl Homework {
                 .map(|it| it.next())
                 .filter_map(|c| c.cloned())
                 .collect();
-            if col.len() == 0 {
-                if row_nums.len() > 0 {
+            if col.is_empty() {
+                if !row_nums.is_empty() {
                     cols.push(std::mem::take(&mut row_nums));
                 }
                 break;
             }
             let tcol = col.trim();
-            if tcol.len() == 0 {
+            if tcol.is_empty() {
                 cols.push(std::mem::take(&mut row_nums));
             } else {
-                row_nums.push(tcol.parse::<i64>().unwrap());
+                row_nums.push(parse_number(tcol)?);
             }
         }
-        Self { cols, opers }
+        log::debug!("Problems, right to left: {:?}", cols);
+        Ok(Self {
+            cols,
+            opers,
+            right_to_left: true,
+        })
     }
 
-    fn compute_all(&self) -> Vec<i64> {
-        self.opers
-            .iter()
-            .zip(self.cols.iter())
-            .map(|(o, col)| o.operate_on(col))
+    fn compute_all(&self, parallel: bool) -> Result<Vec<N>, ProblemError> {
+        let problems: Vec<(&Oper, &Vec<N>)> = self.opers.iter().zip(self.cols.iter()).collect();
+        let answers = map_items(&problems, parallel, |(o, col)| o.operate_on(col));
+        answers
+            .into_iter()
+            .enumerate()
+            .map(|(i, answer)| {
+                answer.map_err(|error| ProblemError {
+                    // The cephalopod layout lists the problems from the right.
+                    column: if self.right_to_left {
+                        self.opers.len() - i
+                    } else {
+                        i + 1
+                    },
+                    error,
+                })
+            })
             .collect()
     }
 }
@@ -149,8 +388,8 @@ mod tests {
 
     #[test]
     fn test_pt1() {
-        let homework = Homework::new_from(INPUT);
-        let answers = homework.compute_all();
+        let homework = Homework::<i64>::new_from(INPUT).unwrap();
+        let answers = homework.compute_all(false).unwrap();
         assert_eq!(answers, vec![33210, 490, 4243455, 401]);
         let grand_total: i64 = answers.iter().sum();
         assert_eq!(grand_total, 4277556);
@@ -158,13 +397,167 @@ mod tests {
 
     #[test]
     fn test_pt2() {
-        let homework = Homework::from_cephalopod(INPUT);
-        for row in homework.cols.iter() {
-            println!("Col: {:?}", row);
-        }
-        let answers = homework.compute_all();
+        let homework = Homework::<i64>::from_cephalopod(INPUT).unwrap();
+        let answers = homework.compute_all(true).unwrap();
         assert_eq!(answers, vec![1058, 3253600, 625, 8544]);
         let grand_total: i64 = answers.iter().sum();
         assert_eq!(grand_total, 3263827);
     }
+
+    static MORE_OPERS: &str = r#"
+64 17 123 15 40 21
+ 3 52  45  9  7  3
+-  /  min max || ^ "#;
+
+    #[test]
+    fn test_more_opers() {
+        // Read top to bottom.
+        let homework = Homework::<i64>::new_from(MORE_OPERS).unwrap();
+        assert_eq!(
+            homework.compute_all(false).unwrap(),
+            vec![64 - 3, 17 / 52, 45, 15, 407, 21i64.pow(3)]
+        );
+
+        // Read right to left, starting with the last problem.
+        let homework = Homework::<i64>::from_cephalopod(MORE_OPERS).unwrap();
+        assert_eq!(
+            homework.cols,
+            vec![
+                vec![13, 2],
+                vec![7, 4],
+                vec![59, 1],
+                vec![35, 24, 1],
+                vec![72, 15],
+                vec![43, 6]
+            ]
+        );
+        assert_eq!(
+            homework.compute_all(false).unwrap(),
+            vec![13i64.pow(2), 74, 59, 1, 72 / 15, 43 - 6]
+        );
+    }
+
+    #[test]
+    fn test_oper_semantics() {
+        let apply = |word: &str, numbers: &[i64]| Oper::from_str(word).unwrap().operate_on(numbers);
+        assert_eq!(apply("-", &[10, 3, 2]), Ok(5));
+        assert_eq!(apply("/", &[100, 7, 2]), Ok(7));
+        assert_eq!(apply("/", &[-7, 2]), Ok(-4));
+        assert_eq!(apply("/", &[7, -2]), Ok(-4));
+        assert_eq!(apply("/", &[-7, -2]), Ok(3));
+        assert_eq!(apply("/", &[-8, 2]), Ok(-4));
+        assert_eq!(apply("/", &[5, 0]), Err(OperError::DivideByZero));
+        assert_eq!(apply("/", &[i64::MIN, -1]), Err(OperError::Overflow));
+        assert_eq!(apply("min", &[4, -2, 9]), Ok(-2));
+        assert_eq!(apply("max", &[4, -2, 9]), Ok(9));
+        assert_eq!(apply("||", &[12, 0, 345]), Ok(120345));
+        assert_eq!(apply("||", &[0, 7]), Ok(7));
+        assert_eq!(
+            apply("||", &[1, -7]),
+            Err(OperError::NegativeConcat("-7".to_string()))
+        );
+        assert_eq!(
+            apply("||", &[922337203, 6854775808]),
+            Err(OperError::Overflow)
+        );
+        assert_eq!(apply("^", &[2, 3, 2]), Ok(512));
+        assert_eq!(apply("^", &[-3, 3]), Ok(-27));
+        assert_eq!(
+            apply("^", &[2, -1]),
+            Err(OperError::NegativeExponent("-1".to_string()))
+        );
+        assert_eq!(apply("^", &[2, 64]), Err(OperError::Overflow));
+        assert_eq!(apply("^", &[7]), Ok(7));
+        assert!(Oper::from_str("%").is_none());
+    }
+
+    static HUGE: &str = r#"
+1 3037000500 4611686018427387904
+2 3037000500 4611686018427387904
++ *          +                   "#;
+
+    #[test]
+    fn test_overflow() {
+        let homework = Homework::<i64>::new_from(HUGE).unwrap();
+        assert_eq!(
+            homework.compute_all(true),
+            Err(ProblemError {
+                column: 2,
+                error: OperError::Overflow
+            })
+        );
+
+        // The cephalopod reading lists the problems from the right.
+        let homework = Homework {
+            cols: vec![vec![1, 2], vec![i64::MAX, 1]],
+            opers: vec![Oper::Plus, Oper::Plus],
+            right_to_left: true,
+        };
+        let err = homework.compute_all(false).unwrap_err();
+        assert_eq!(err.column, 1);
+        assert_eq!(err.to_string(), "Problem 1 failed: overflow");
+
+        let answers = [i64::MAX - 1, 1, 1];
+        assert_eq!(grand_total(&answers[..2]), Ok(i64::MAX));
+        assert_eq!(grand_total(&answers), Err(OperError::Overflow));
+
+        let err = Homework::<i64>::new_from(BEYOND_I64).err().unwrap();
+        assert_eq!(err.to_string(), "Bad number \"99999999999999999999\"");
+        assert!(Homework::<i64>::new_from("1\n%").is_err());
+    }
+
+    static BEYOND_I64: &str = r#"
+99999999999999999999 9
+                   1 999999999
+*                    ^         "#;
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_bigint_exact() {
+        use num_bigint::BigInt;
+
+        let homework = Homework::<BigInt>::new_from(HUGE).unwrap();
+        let answers = homework.compute_all(true).unwrap();
+        let expected: Vec<BigInt> = ["3", "9223372037000250000", "9223372036854775808"]
+            .iter()
+            .map(|n| n.parse().unwrap())
+            .collect();
+        assert_eq!(answers, expected);
+        assert_eq!(
+            grand_total(&answers).unwrap().to_string(),
+            "18446744073855025811"
+        );
+
+        let apply = |word: &str, numbers: &[i64]| {
+            let numbers: Vec<BigInt> = numbers.iter().map(|&n| n.into()).collect();
+            Oper::from_str(word).unwrap().operate_on(&numbers)
+        };
+        assert_eq!(apply("^", &[2, 64]), Ok(BigInt::from(1u128 << 64)));
+        assert_eq!(apply("/", &[-7, 2]), Ok(BigInt::from(-4)));
+        assert_eq!(
+            apply("||", &[922337203, 6854775808]).unwrap().to_string(),
+            "9223372036854775808"
+        );
+        assert_eq!(apply("^", &[-1, 999999999]), Ok(BigInt::from(-1)));
+
+        // Operands too big for an i64 are read exactly, but a power with
+        // nearly a billion digits is refused.
+        let homework = Homework::<BigInt>::new_from(BEYOND_I64).unwrap();
+        assert_eq!(
+            homework.compute_all(false),
+            Err(ProblemError {
+                column: 2,
+                error: OperError::Overflow
+            })
+        );
+        let homework = Homework::<BigInt> {
+            cols: homework.cols[..1].to_vec(),
+            opers: vec![Oper::Mult],
+            right_to_left: false,
+        };
+        assert_eq!(
+            homework.compute_all(false).unwrap()[0].to_string(),
+            "99999999999999999999"
+        );
+    }
 }
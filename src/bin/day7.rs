@@ -1,4 +1,4 @@
-use aoc2025::get_input;
+use aoc2025::{get_input, init_logging};
 use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::BufReader;
@@ -25,7 +25,7 @@ impl Manifold {
 
     fn first_line(&mut self, line: &str) {
         let tline = line.trim();
-        if tline == "" {
+        if tline.is_empty() {
             return;
         }
         self.width = 0;
@@ -53,7 +53,7 @@ impl Manifold {
                 }
                 BLANK => (),
                 _ => {
-                    println!("Unrecognized line element {}", ch);
+                    log::warn!("Unrecognized line element {}", ch);
                 }
             }
         }
@@ -83,6 +83,7 @@ fn process_manifold<R: Read>(m: &mut Manifold, rdr: &mut R) {
 }
 
 fn main() {
+    init_logging();
     let input_fn = get_input("input_d7.txt");
     let mut input = File::open(&input_fn).unwrap();
     {
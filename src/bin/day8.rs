@@ -1,10 +1,10 @@
 use std::collections::HashMap;
 use std::fmt;
 
-use aoc2025::get_input_string;
+use aoc2025::{get_input_string, init_logging};
 
 fn main() {
-    env_logger::init();
+    init_logging();
     let input = get_input_string("input_d8.txt").unwrap();
     {
         let mut wiring = Wiring::new(&input);
@@ -84,7 +84,7 @@ struct Wiring {
 impl Wiring {
     fn new(in_str: &str) -> Self {
         Self {
-            jbs: in_str.trim().lines().map(|l| JB::from_str(l)).collect(),
+            jbs: in_str.trim().lines().map(JB::from_str).collect(),
             cluster: HashMap::new(),
             next_cluster_id: 0,
         }
@@ -1,7 +1,7 @@
-use aoc2025::get_input_string;
+use aoc2025::{get_input_string, init_logging};
 
 fn main() {
-    env_logger::init();
+    init_logging();
     let input = get_input_string("input_d9.txt").unwrap();
     let tiles = input_as_tiles(&input);
     {
@@ -76,10 +76,13 @@ enum Dir {
     W,
 }
 
+#[cfg(test)]
 const NUM_DIRS: usize = 4; // std::mem::variant_count::<Dir>() is unstable
+#[cfg(test)]
 static CW_TURNS: [[i32; NUM_DIRS]; NUM_DIRS] =
     [[0, 1, 2, -1], [-1, 0, 1, 2], [2, -1, 0, 1], [1, 2, -1, 0]];
 
+#[cfg(test)]
 fn right_turns(e1: &Edge, e2: &Edge) -> i32 {
     CW_TURNS[e1.dir as usize][e2.dir as usize]
 }
@@ -354,10 +357,6 @@ mod tests {
         assert_eq!(min_edge_length, 5);
     }
 
-    fn edge_len(e: &Edge) -> i64 {
-        e.max_coord - e.min_coord + 1
-    }
-
     #[test]
     fn test_pt2() {
         log_init();
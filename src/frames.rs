@@ -0,0 +1,269 @@
+//! Writes frames of a palette-indexed picture as PPM images or an animated GIF.
+
+use anyhow::{Result, ensure};
+use std::collections::HashMap;
+use std::io::Write;
+
+pub type Rgb = [u8; 3];
+
+/// A picture where each pixel is an index into a palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    /// Palette indices, row by row.
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// Builds a frame from a grid of cells, drawing each as a `scale` pixel square.
+    pub fn from_cells(cells: &[Vec<u8>], scale: usize) -> Self {
+        let width = cells.first().map_or(0, Vec::len) * scale;
+        let mut pixels = Vec::with_capacity(width * cells.len() * scale);
+        for row in cells {
+            let line: Vec<u8> = row
+                .iter()
+                .flat_map(|&cell| std::iter::repeat_n(cell, scale))
+                .collect();
+            for _ in 0..scale {
+                pixels.extend_from_slice(&line);
+            }
+        }
+        Frame {
+            width,
+            height: cells.len() * scale,
+            pixels,
+        }
+    }
+}
+
+/// Writes a frame as a binary PPM image.
+pub fn write_ppm<W: Write>(out: &mut W, frame: &Frame, palette: &[Rgb]) -> Result<()> {
+    write!(out, "P6\n{} {}\n255\n", frame.width, frame.height)?;
+    let mut data = Vec::with_capacity(frame.pixels.len() * 3);
+    for &px in &frame.pixels {
+        data.extend_from_slice(&palette[px as usize]);
+    }
+    out.write_all(&data)?;
+    Ok(())
+}
+
+/// Writes frames as a looping animated GIF, showing each for `delay_cs` hundredths of a second.
+///
+/// All frames must be the same size, and the palette can hold at most 256 colours.
+pub fn write_gif<W: Write>(
+    out: &mut W,
+    frames: &[Frame],
+    palette: &[Rgb],
+    delay_cs: u16,
+) -> Result<()> {
+    ensure!(!frames.is_empty(), "An animation needs at least one frame");
+    ensure!(
+        (1..=256).contains(&palette.len()),
+        "A GIF palette has 1 to 256 colours, not {}",
+        palette.len()
+    );
+    let (width, height) = (frames[0].width, frames[0].height);
+    ensure!(
+        frames
+            .iter()
+            .all(|f| (f.width, f.height) == (width, height)),
+        "Every frame must be {width}x{height}"
+    );
+    ensure!(
+        width <= u16::MAX as usize && height <= u16::MAX as usize,
+        "A GIF can be at most {} pixels across",
+        u16::MAX
+    );
+    // The colour table holds a power of two entries, at least four.
+    let table_bits = palette.len().next_power_of_two().trailing_zeros().max(2);
+
+    out.write_all(b"GIF89a")?;
+    out.write_all(&(width as u16).to_le_bytes())?;
+    out.write_all(&(height as u16).to_le_bytes())?;
+    out.write_all(&[0xf0 | (table_bits as u8 - 1), 0, 0])?;
+    for i in 0..1 << table_bits {
+        out.write_all(palette.get(i).unwrap_or(&[0, 0, 0]))?;
+    }
+    // Loop forever.
+    out.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+
+    for frame in frames {
+        out.write_all(&[0x21, 0xf9, 4, 0x04])?;
+        out.write_all(&delay_cs.to_le_bytes())?;
+        out.write_all(&[0, 0])?;
+        out.write_all(&[0x2c, 0, 0, 0, 0])?;
+        out.write_all(&(width as u16).to_le_bytes())?;
+        out.write_all(&(height as u16).to_le_bytes())?;
+        out.write_all(&[0, table_bits as u8])?;
+        for block in lzw_encode(&frame.pixels, table_bits).chunks(255) {
+            out.write_all(&[block.len() as u8])?;
+            out.write_all(block)?;
+        }
+        out.write_all(&[0])?;
+    }
+    out.write_all(&[0x3b])?;
+    Ok(())
+}
+
+/// Packs codes of varying width into bytes, least significant bit first.
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    num_bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u32) {
+        self.acc |= (code as u32) << self.num_bits;
+        self.num_bits += width;
+        while self.num_bits >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.num_bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.num_bits > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Compresses pixels with the variable-width LZW coding GIF uses.
+fn lzw_encode(pixels: &[u8], min_bits: u32) -> Vec<u8> {
+    const MAX_CODES: u16 = 1 << 12;
+    let clear = 1u16 << min_bits;
+    let end = clear + 1;
+    let mut out = BitWriter {
+        bytes: Vec::new(),
+        acc: 0,
+        num_bits: 0,
+    };
+    let mut codes: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut width = min_bits + 1;
+    let mut next = end + 1;
+    out.write(clear, width);
+
+    let Some((&first, rest)) = pixels.split_first() else {
+        out.write(end, width);
+        return out.finish();
+    };
+    let mut prefix = first as u16;
+    for &px in rest {
+        if let Some(&code) = codes.get(&(prefix, px)) {
+            prefix = code;
+            continue;
+        }
+        out.write(prefix, width);
+        // The decoder widens its codes one entry behind us.
+        if next >= 1 << width && width < 12 {
+            width += 1;
+        }
+        if next < MAX_CODES {
+            codes.insert((prefix, px), next);
+            next += 1;
+        } else {
+            out.write(clear, width);
+            codes.clear();
+            width = min_bits + 1;
+            next = end + 1;
+        }
+        prefix = px as u16;
+    }
+    out.write(prefix, width);
+    if next >= 1 << width && width < 12 {
+        width += 1;
+    }
+    out.write(end, width);
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lcg;
+
+    /// Reads back what `lzw_encode` wrote.
+    fn lzw_decode(bytes: &[u8], min_bits: u32) -> Vec<u8> {
+        let clear = 1usize << min_bits;
+        let mut pos = 0;
+        let mut read = |width: usize| {
+            let code = (0..width)
+                .map(|i| ((bytes[(pos + i) / 8] >> ((pos + i) % 8)) & 1) as usize)
+                .rev()
+                .fold(0, |acc, bit| acc << 1 | bit);
+            pos += width;
+            code
+        };
+        let mut out = Vec::new();
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut width = 0;
+        let mut prev: Option<Vec<u8>> = None;
+        loop {
+            let code = read(width.max(min_bits as usize + 1));
+            if code == clear {
+                table = (0..clear).map(|i| vec![i as u8]).collect();
+                table.extend([Vec::new(), Vec::new()]);
+                width = min_bits as usize + 1;
+                prev = None;
+                continue;
+            }
+            if code == clear + 1 {
+                return out;
+            }
+            let entry = match (table.get(code), &prev) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(p)) => [p.as_slice(), &p[..1]].concat(),
+                (None, None) => panic!("Code {code} before any entry"),
+            };
+            out.extend_from_slice(&entry);
+            if let Some(p) = prev
+                && table.len() < 4096
+            {
+                table.push([p.as_slice(), &entry[..1]].concat());
+            }
+            if table.len() >= 1 << width && width < 12 {
+                width += 1;
+            }
+            prev = Some(entry);
+        }
+    }
+
+    #[test]
+    fn test_lzw_round_trip() {
+        let mut rng = Lcg::new(39);
+        let noise: Vec<u8> = (0..50_000).map(|_| rng.below(7) as u8).collect();
+        let runs: Vec<u8> = (0..20_000).map(|i| (i / 37 % 3) as u8).collect();
+        for pixels in [vec![], vec![1], vec![0, 0, 0, 0, 0, 0, 0], noise, runs] {
+            assert_eq!(lzw_decode(&lzw_encode(&pixels, 3), 3), pixels);
+        }
+    }
+
+    #[test]
+    fn test_write_images() {
+        let frame = Frame::from_cells(&[vec![0, 1], vec![2, 0]], 2);
+        assert_eq!(
+            frame.pixels,
+            [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 0, 0, 2, 2, 0, 0]
+        );
+        let palette = [[0, 0, 0], [255, 0, 0], [0, 0, 255]];
+
+        let mut ppm = Vec::new();
+        write_ppm(&mut ppm, &frame, &palette).unwrap();
+        assert!(ppm.starts_with(b"P6\n4 4\n255\n"));
+        assert_eq!(ppm.len(), 11 + 4 * 4 * 3);
+        assert_eq!(ppm[11 + 6..11 + 9], [255, 0, 0]);
+
+        let mut gif = Vec::new();
+        write_gif(&mut gif, &[frame.clone(), frame.clone()], &palette, 10).unwrap();
+        assert!(gif.starts_with(b"GIF89a\x04\x00\x04\x00\xf1"));
+        assert_eq!(gif.last(), Some(&0x3b));
+
+        let small = Frame::from_cells(&[vec![0]], 1);
+        assert!(write_gif(&mut Vec::new(), &[frame, small], &palette, 10).is_err());
+        assert!(write_gif(&mut Vec::new(), &[], &palette, 10).is_err());
+    }
+}
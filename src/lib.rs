@@ -1,5 +1,11 @@
+pub mod frames;
+
 use anyhow::{Context as _, Result};
+use log::LevelFilter;
+use std::fmt::Debug;
 use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Instant;
 
 pub fn get_input(filename: &str) -> PathBuf {
     Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -11,3 +17,169 @@ pub fn get_input_string(filename: &str) -> Result<String> {
     let file_path = get_input(filename);
     std::fs::read_to_string(&file_path).with_context(|| format!("Reading file {}", filename))
 }
+
+/// The most digits `proper_divisors` covers: a u128 written in binary.
+pub const MAX_NUM_DIGITS: u32 = 128;
+
+static PROPER_DIVISORS: LazyLock<Vec<Vec<u32>>> = LazyLock::new(|| {
+    let mut table = vec![Vec::new(); MAX_NUM_DIGITS as usize + 1];
+    for divisor in 1..=MAX_NUM_DIGITS / 2 {
+        for multiple in (2 * divisor..=MAX_NUM_DIGITS).step_by(divisor as usize) {
+            table[multiple as usize].push(divisor);
+        }
+    }
+    table
+});
+
+/// Divisors of `n` other than `n` itself, in increasing order. These are the
+/// pattern lengths a number with `n` digits can be made of. The table is
+/// built on first use, so lookups don't allocate.
+///
+/// Panics if `n` is greater than `MAX_NUM_DIGITS`.
+pub fn proper_divisors(n: u32) -> &'static [u32] {
+    &PROPER_DIVISORS[n as usize]
+}
+
+/// Maps `f` over `items`, on a thread pool if `parallel` is set and the crate
+/// was built with the `parallel` feature. Results are in the order of `items` either way.
+pub fn map_items<T, R, F>(items: &[T], parallel: bool, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    if parallel {
+        use rayon::prelude::*;
+        return items.par_iter().map(f).collect();
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = parallel;
+    items.iter().map(f).collect()
+}
+
+/// A seeded linear congruential generator, for tests that want the same
+/// random-looking input on every run.
+#[derive(Debug, Clone)]
+pub struct Lcg(u32);
+
+impl Lcg {
+    pub fn new(seed: u32) -> Self {
+        Self(seed)
+    }
+
+    /// The next number, from the generator's better-mixed high 15 bits.
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345);
+        self.0 >> 16
+    }
+
+    /// The next number below `n`.
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.next_u32() % n
+    }
+}
+
+/// Whether `name` was given on the command line.
+pub fn has_flag(name: &str) -> bool {
+    std::env::args().skip(1).any(|arg| arg == name)
+}
+
+/// The argument following `name` on the command line, if it was given.
+pub fn flag_value(name: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != name).nth(1)
+}
+
+/// How many `-v` or `--verbose` flags are on the command line; `-vv` counts twice.
+fn verbosity<I: IntoIterator<Item = String>>(args: I) -> usize {
+    args.into_iter()
+        .map(|arg| match arg.strip_prefix('-') {
+            Some("-verbose") => 1,
+            Some(vs) if !vs.is_empty() && vs.bytes().all(|b| b == b'v') => vs.len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Sends `log` output to stderr, so it never mixes with the answers.
+///
+/// Only warnings are shown by default; `-v` adds info, `-vv` debug and
+/// `-vvv` trace. `RUST_LOG` is read after that, so it can still pick out
+/// single modules.
+pub fn init_logging() {
+    let level = match verbosity(std::env::args().skip(1)) {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .init();
+}
+
+/// Runs `work`, which is told whether to use `map_items` in parallel.
+///
+/// Given `--compare` on the command line, it's run both ways instead, with
+/// the timings printed to stderr under `label`, and the answers checked to agree.
+pub fn run_compared<R, F>(label: &str, work: F) -> R
+where
+    R: PartialEq + Debug,
+    F: Fn(bool) -> R,
+{
+    if !has_flag("--compare") {
+        return work(true);
+    }
+    let start = Instant::now();
+    let sequential = work(false);
+    let sequential_time = start.elapsed();
+    let start = Instant::now();
+    let parallel = work(true);
+    let parallel_time = start.elapsed();
+    eprintln!(
+        "{}: sequential {:?}, parallel {:?}{}",
+        label,
+        sequential_time,
+        parallel_time,
+        if cfg!(feature = "parallel") {
+            ""
+        } else {
+            " (built without the parallel feature)"
+        }
+    );
+    assert_eq!(sequential, parallel, "{}: parallel answer differs", label);
+    parallel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity() {
+        let count = |args: &[&str]| verbosity(args.iter().map(|a| a.to_string()));
+        assert_eq!(count(&[]), 0);
+        assert_eq!(count(&["--compare", "-"]), 0);
+        assert_eq!(count(&["-v"]), 1);
+        assert_eq!(count(&["--verbose", "-vv"]), 3);
+        assert_eq!(count(&["-vx", "--verbosity", "v"]), 0);
+    }
+
+    #[test]
+    fn test_proper_divisors() {
+        assert_eq!(proper_divisors(12), &[1, 2, 3, 4, 6]);
+        assert_eq!(proper_divisors(2), &[1]);
+        assert_eq!(proper_divisors(1), &[] as &[u32]);
+        assert_eq!(proper_divisors(39), &[1, 3, 13]);
+        assert_eq!(proper_divisors(MAX_NUM_DIGITS), &[1, 2, 4, 8, 16, 32, 64]);
+    }
+
+    #[test]
+    fn test_map_items_keeps_order() {
+        let items: Vec<u64> = (0..10_000).collect();
+        let expected: Vec<u64> = items.iter().map(|i| i * i).collect();
+        assert_eq!(map_items(&items, false, |i| i * i), expected);
+        assert_eq!(map_items(&items, true, |i| i * i), expected);
+    }
+}